@@ -0,0 +1,275 @@
+use crate::transform::rgba;
+use crate::variants::{Params, Variants};
+use image::{Rgba, RgbaImage};
+use noise::{Fbm, NoiseFn, OpenSimplex, Seedable};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Background {
+    VerticalGradient(Rgba<u8>, Rgba<u8>),
+    HorizontalGradient(Rgba<u8>, Rgba<u8>),
+    Radial((f32, f32), f32, Rgba<u8>, Rgba<u8>),
+    Stripes(f32, f32, Rgba<u8>, Rgba<u8>),
+    Checkerboard(u32, Rgba<u8>, Rgba<u8>),
+    Perlin(f64, u32, Rgba<u8>, Rgba<u8>),
+    Simplex(f64, u32, Rgba<u8>, Rgba<u8>),
+    Voronoi(u32, u64),
+    Tiles(u32, u64),
+}
+
+fn lerp(start: &Rgba<u8>, end: &Rgba<u8>, t: f32) -> Rgba<u8> {
+    let t = t.clamp(0.0, 1.0);
+    let mut out = *start;
+    out.0
+        .iter_mut()
+        .zip(end.0.iter())
+        .for_each(|(s, e)| *s = (*s as f32 * (1.0 - t) + *e as f32 * t).round() as u8);
+    out
+}
+
+/// Splits frames into their 8x8 background tiles, dropping duplicates and
+/// tiles of a single flat color.
+pub fn tiles(frames: &[RgbaImage]) -> Vec<RgbaImage> {
+    let mut tiles: Vec<RgbaImage> = vec![];
+    let mut seen = HashSet::new();
+    for f in frames {
+        for y in (0..f.height() / 8).map(|y| y * 8) {
+            for x in (0..f.width() / 8).map(|x| x * 8) {
+                let tile = image::imageops::crop_imm(f, x, y, 8, 8).to_image();
+                let first = tile.get_pixel(0, 0);
+                if tile.pixels().all(|p| p == first) {
+                    continue;
+                }
+                if seen.insert(tile.as_raw().clone()) {
+                    tiles.push(tile);
+                }
+            }
+        }
+    }
+    tiles
+}
+
+type Generator<R> = Box<dyn Fn(&mut R, Params, (u32, u32)) -> Background>;
+
+impl Background {
+    pub fn random<R>(mut rng: R, cfg: &Variants, dim: (u32, u32), has_tiles: bool) -> Background
+    where
+        R: Rng,
+    {
+        let v: Vec<(_, _, Generator<R>)> = vec![
+            (
+                "vertical_gradient",
+                1,
+                Box::new(|mut rng, _, _| {
+                    Background::VerticalGradient(
+                        rgba(&mut rng, Some(0xff)),
+                        rgba(&mut rng, Some(0xff)),
+                    )
+                }),
+            ),
+            (
+                "horizontal_gradient",
+                1,
+                Box::new(|mut rng, _, _| {
+                    Background::HorizontalGradient(
+                        rgba(&mut rng, Some(0xff)),
+                        rgba(&mut rng, Some(0xff)),
+                    )
+                }),
+            ),
+            (
+                "radial",
+                1,
+                Box::new(|mut rng, p, (w, h)| {
+                    Background::Radial(
+                        (rng.gen_range(0.0..w as f32), rng.gen_range(0.0..h as f32)),
+                        p.f32(&mut rng, "radius", 100.0..600.0),
+                        rgba(&mut rng, Some(0xff)),
+                        rgba(&mut rng, Some(0xff)),
+                    )
+                }),
+            ),
+            (
+                "stripes",
+                1,
+                Box::new(|mut rng, p, _| {
+                    Background::Stripes(
+                        p.f32(&mut rng, "width", 8.0..64.0),
+                        p.f32(&mut rng, "angle", 0.0..180.0),
+                        rgba(&mut rng, Some(0xff)),
+                        rgba(&mut rng, Some(0xff)),
+                    )
+                }),
+            ),
+            (
+                "checkerboard",
+                1,
+                Box::new(|mut rng, p, _| {
+                    Background::Checkerboard(
+                        p.u32(&mut rng, "size", 8..64),
+                        rgba(&mut rng, Some(0xff)),
+                        rgba(&mut rng, Some(0xff)),
+                    )
+                }),
+            ),
+            (
+                "perlin",
+                1,
+                Box::new(|mut rng, p, _| {
+                    Background::Perlin(
+                        p.f64(&mut rng, "scale", 20.0..200.0),
+                        rng.gen(),
+                        rgba(&mut rng, Some(0xff)),
+                        rgba(&mut rng, Some(0xff)),
+                    )
+                }),
+            ),
+            (
+                "simplex",
+                1,
+                Box::new(|mut rng, p, _| {
+                    Background::Simplex(
+                        p.f64(&mut rng, "scale", 20.0..200.0),
+                        rng.gen(),
+                        rgba(&mut rng, Some(0xff)),
+                        rgba(&mut rng, Some(0xff)),
+                    )
+                }),
+            ),
+            (
+                "voronoi",
+                1,
+                Box::new(|mut rng, p, _| {
+                    Background::Voronoi(p.u32(&mut rng, "cells", 4..40), rng.gen())
+                }),
+            ),
+            (
+                "tiles",
+                1,
+                Box::new(|mut rng, p, _| {
+                    Background::Tiles(p.u32(&mut rng, "scale", 1..5), rng.gen())
+                }),
+            ),
+        ];
+        let v = v
+            .into_iter()
+            .filter(|(name, _, _)| has_tiles || *name != "tiles")
+            .collect::<Vec<_>>();
+        match cfg.choose(&mut rng, &v) {
+            Some((name, _, f)) => f(&mut rng, cfg.params(name), dim),
+            // Everything disabled, fall back to the white the panel is
+            // cleared to.
+            None => {
+                let white = Rgba([0xff, 0xff, 0xff, 0xff]);
+                Background::VerticalGradient(white, white)
+            }
+        }
+    }
+
+    pub fn render(&self, base: &mut RgbaImage, tiles: &[RgbaImage]) {
+        match *self {
+            Background::VerticalGradient(start, end) => {
+                image::imageops::vertical_gradient(base, &start, &end);
+            }
+            Background::HorizontalGradient(start, end) => {
+                image::imageops::horizontal_gradient(base, &start, &end);
+            }
+            Background::Radial((cx, cy), radius, inner, outer) => {
+                base.enumerate_pixels_mut().for_each(|(x, y, p)| {
+                    let d = ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt();
+                    *p = lerp(&inner, &outer, d / radius);
+                });
+            }
+            Background::Stripes(width, angle, c1, c2) => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                base.enumerate_pixels_mut().for_each(|(x, y, p)| {
+                    let d = x as f32 * cos + y as f32 * sin;
+                    *p = if (d / width).floor() as i64 % 2 == 0 {
+                        c1
+                    } else {
+                        c2
+                    };
+                });
+            }
+            Background::Checkerboard(size, c1, c2) => {
+                let size = size.max(1);
+                base.enumerate_pixels_mut().for_each(|(x, y, p)| {
+                    *p = if (x / size + y / size) % 2 == 0 {
+                        c1
+                    } else {
+                        c2
+                    };
+                });
+            }
+            Background::Perlin(scale, seed, c1, c2) => {
+                fill_noise(base, &Fbm::new().set_seed(seed), scale, &c1, &c2)
+            }
+            Background::Simplex(scale, seed, c1, c2) => {
+                fill_noise(base, &OpenSimplex::new().set_seed(seed), scale, &c1, &c2)
+            }
+            Background::Voronoi(cells, seed) => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let (w, h) = base.dimensions();
+                let points = (0..cells.max(1))
+                    .map(|_| {
+                        (
+                            rng.gen_range(0.0..w as f32),
+                            rng.gen_range(0.0..h as f32),
+                            rgba(&mut rng, Some(0xff)),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                base.enumerate_pixels_mut().for_each(|(x, y, p)| {
+                    let (x, y) = (x as f32, y as f32);
+                    *p = points
+                        .iter()
+                        .min_by(|a, b| {
+                            let da = (a.0 - x).powi(2) + (a.1 - y).powi(2);
+                            let db = (b.0 - x).powi(2) + (b.1 - y).powi(2);
+                            da.partial_cmp(&db).unwrap()
+                        })
+                        .unwrap()
+                        .2;
+                });
+            }
+            Background::Tiles(scale, seed) => {
+                if tiles.is_empty() {
+                    return;
+                }
+                let mut rng = StdRng::seed_from_u64(seed);
+                let scale = scale.max(1);
+                let count = rng.gen_range(1..=tiles.len().min(4));
+                let picks = tiles.choose_multiple(&mut rng, count).collect::<Vec<_>>();
+                let size = 8 * scale;
+                let (w, h) = base.dimensions();
+                for ty in 0..(h + size - 1) / size {
+                    for tx in 0..(w + size - 1) / size {
+                        let tile = picks.choose(&mut rng).unwrap();
+                        for y in 0..size.min(h - ty * size) {
+                            for x in 0..size.min(w - tx * size) {
+                                base.put_pixel(
+                                    tx * size + x,
+                                    ty * size + y,
+                                    *tile.get_pixel(x / scale, y / scale),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn fill_noise<N>(base: &mut RgbaImage, noise: &N, scale: f64, c1: &Rgba<u8>, c2: &Rgba<u8>)
+where
+    N: NoiseFn<[f64; 2]>,
+{
+    base.enumerate_pixels_mut().for_each(|(x, y, p)| {
+        let v = noise.get([x as f64 / scale, y as f64 / scale]);
+        *p = lerp(c1, c2, ((v + 1.0) / 2.0) as f32);
+    });
+}
@@ -0,0 +1,96 @@
+use crate::variants::Variants;
+use image::RgbaImage;
+use rand::Rng;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Blend {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Difference,
+    Lighten,
+    Darken,
+    ColorDodge,
+    Xor,
+}
+
+impl Blend {
+    /// Picks a blend mode from the `[blend]` menu, where plain alpha-over is
+    /// the most likely by default.
+    pub fn random<R>(rng: R, cfg: &Variants) -> Blend
+    where
+        R: Rng,
+    {
+        let v = [
+            ("normal", 8, Blend::Normal),
+            ("multiply", 1, Blend::Multiply),
+            ("screen", 1, Blend::Screen),
+            ("overlay", 1, Blend::Overlay),
+            ("difference", 1, Blend::Difference),
+            ("lighten", 1, Blend::Lighten),
+            ("darken", 1, Blend::Darken),
+            ("color_dodge", 1, Blend::ColorDodge),
+            ("xor", 1, Blend::Xor),
+        ];
+        cfg.choose(rng, &v)
+            .map(|(_, _, b)| *b)
+            .unwrap_or(Blend::Normal)
+    }
+
+    /// Blends one channel of the backdrop `b` with the source `s`, both in
+    /// 0..=1.
+    fn channel(&self, b: f32, s: f32) -> f32 {
+        match self {
+            Blend::Normal => s,
+            Blend::Multiply => b * s,
+            Blend::Screen => b + s - b * s,
+            Blend::Overlay => {
+                if b <= 0.5 {
+                    2.0 * b * s
+                } else {
+                    1.0 - 2.0 * (1.0 - b) * (1.0 - s)
+                }
+            }
+            Blend::Difference => (b - s).abs(),
+            Blend::Lighten => b.max(s),
+            Blend::Darken => b.min(s),
+            Blend::ColorDodge => {
+                if b == 0.0 {
+                    0.0
+                } else if s >= 1.0 {
+                    1.0
+                } else {
+                    (b / (1.0 - s)).min(1.0)
+                }
+            }
+            Blend::Xor => {
+                (((b * 255.0).round() as u8) ^ ((s * 255.0).round() as u8)) as f32 / 255.0
+            }
+        }
+    }
+
+    /// Composites `top` over `bottom` (same size), blending colors where both
+    /// are visible and falling back to plain alpha-over elsewhere.
+    pub fn composite(&self, bottom: &mut RgbaImage, top: &RgbaImage) {
+        if *self == Blend::Normal {
+            image::imageops::overlay(bottom, top, 0, 0);
+            return;
+        }
+        bottom
+            .pixels_mut()
+            .zip(top.pixels())
+            .filter(|(_, t)| t[3] > 0)
+            .for_each(|(p, t)| {
+                let (ab, as_) = (p[3] as f32 / 255.0, t[3] as f32 / 255.0);
+                let ao = as_ + ab * (1.0 - as_);
+                for c in 0..3 {
+                    let (cb, cs) = (p[c] as f32 / 255.0, t[c] as f32 / 255.0);
+                    let mixed = (1.0 - ab) * cs + ab * self.channel(cb, cs);
+                    let co = as_ * mixed + (1.0 - as_) * ab * cb;
+                    p[c] = (co / ao * 255.0).round().clamp(0.0, 255.0) as u8;
+                }
+                p[3] = (ao * 255.0).round() as u8;
+            });
+    }
+}
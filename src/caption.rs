@@ -0,0 +1,186 @@
+use crate::palette::PaletteConfig;
+use crate::roms::Rom;
+use crate::text::{self, Face, TextStyle};
+use anyhow::{anyhow, Result};
+use epd_waveshare::color::OctColor;
+use image::RgbaImage;
+use serde_derive::Deserialize;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    Top,
+    Bottom,
+}
+
+/// Caption colors are restricted to the panel inks so the band and text
+/// come through dithering untouched.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Ink {
+    Black,
+    White,
+    Green,
+    Blue,
+    Red,
+    Yellow,
+    Orange,
+}
+
+impl From<Ink> for OctColor {
+    fn from(ink: Ink) -> OctColor {
+        match ink {
+            Ink::Black => OctColor::Black,
+            Ink::White => OctColor::White,
+            Ink::Green => OctColor::Green,
+            Ink::Blue => OctColor::Blue,
+            Ink::Red => OctColor::Red,
+            Ink::Yellow => OctColor::Yellow,
+            Ink::Orange => OctColor::Orange,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleSource {
+    Name,
+    Header,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CaptionConfig {
+    pub position: Position,
+    pub font: String,
+    pub ttf: Vec<PathBuf>,
+    pub size: f32,
+    pub weight: u8,
+    pub fg: Ink,
+    pub bg: Ink,
+    pub outline: Option<Ink>,
+    pub outline_width: u8,
+    pub shadow: Option<Ink>,
+    pub shadow_offset: (i32, i32),
+    pub title: TitleSource,
+    pub timestamp: String,
+    pub padding: u32,
+}
+
+impl Default for CaptionConfig {
+    fn default() -> Self {
+        CaptionConfig {
+            position: Position::Bottom,
+            font: "9x15".to_string(),
+            ttf: vec![],
+            size: 20.0,
+            weight: 0,
+            fg: Ink::White,
+            bg: Ink::Black,
+            outline: None,
+            outline_width: 1,
+            shadow: None,
+            shadow_offset: (2, 2),
+            title: TitleSource::Name,
+            timestamp: "%Y-%m-%d %H:%M".to_string(),
+            padding: 4,
+        }
+    }
+}
+
+fn rgba(palette: &PaletteConfig, ink: Ink) -> image::Rgba<u8> {
+    let [r, g, b] = palette.rgb(ink.into()).0;
+    image::Rgba([r, g, b, 0xff])
+}
+
+impl CaptionConfig {
+    /// Rejects a `timestamp` chrono can't format, which would otherwise
+    /// panic in the middle of a render.
+    pub fn check(&self) -> Result<()> {
+        use chrono::format::{Item, StrftimeItems};
+        if StrftimeItems::new(&self.timestamp).any(|i| i == Item::Error) {
+            return Err(anyhow!("Invalid caption timestamp {:?}", self.timestamp));
+        }
+        Ok(())
+    }
+
+    /// Loads the fonts, once, for `draw`.
+    pub fn face(&self) -> Result<Face> {
+        if self.ttf.is_empty() {
+            Face::mono(&self.font)
+        } else {
+            Face::load(&self.ttf)
+        }
+    }
+
+    pub fn lines(&self, rom: &Rom) -> Vec<String> {
+        let title = match self.title {
+            TitleSource::Header => rom
+                .header_title()
+                .ok()
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| rom.name.clone()),
+            TitleSource::Name => rom.name.clone(),
+        };
+        let info = rom
+            .region()
+            .map(|r| r.to_string())
+            .into_iter()
+            .chain(rom.year.map(|y| y.to_string()))
+            .chain(std::iter::once(
+                chrono::Local::now().format(&self.timestamp).to_string(),
+            ))
+            .collect::<Vec<_>>()
+            .join(" - ");
+        vec![title, info]
+    }
+
+    /// Draws the caption band into `overlay`, which is composited over the
+    /// image before dithering and stamped back on afterwards.
+    pub fn draw(&self, face: &Face, overlay: &mut RgbaImage, rom: &Rom, palette: &PaletteConfig) {
+        let lines = self.lines(rom);
+        let (width, height) = overlay.dimensions();
+        let extra =
+            self.weight as u32 + self.outline.map(|_| self.outline_width as u32).unwrap_or(0);
+        let line_height = face.line_height(self.size) + 2 * extra;
+        let band = lines.len() as u32 * line_height + 2 * self.padding;
+        let top = match self.position {
+            Position::Top => 0,
+            Position::Bottom => height.saturating_sub(band),
+        };
+        let bg = rgba(palette, self.bg);
+        for y in top..(top + band).min(height) {
+            for x in 0..width {
+                overlay.put_pixel(x, y, bg);
+            }
+        }
+
+        let style = TextStyle {
+            size: self.size,
+            weight: self.weight,
+            color: rgba(palette, self.fg),
+            outline: self
+                .outline
+                .map(|ink| (self.outline_width, rgba(palette, ink))),
+            shadow: self
+                .shadow
+                .map(|ink| (self.shadow_offset, rgba(palette, ink))),
+        };
+        let max_width = width.saturating_sub(2 * (self.padding + extra));
+        for (i, line) in lines.iter().enumerate() {
+            let mut line = line.clone();
+            while face.width(&line, self.size) > max_width && line.pop().is_some() {}
+            text::draw(
+                overlay,
+                face,
+                &line,
+                (
+                    (self.padding + extra) as i32,
+                    (top + self.padding + extra + i as u32 * line_height) as i32,
+                ),
+                &style,
+            );
+        }
+    }
+}
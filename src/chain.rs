@@ -0,0 +1,300 @@
+use crate::transform::{Alpha, Transform, Transformable};
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, Rgba};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A sequence of transforms applied in order, written as e.g.
+/// `blur(3.2) | edges(0.1,0.8,#ff0000,#000000)`. Names match the ones used
+/// to configure the random transform menu, arguments are the variant's
+/// fields in order and colors are `#rrggbb` or `#rrggbbaa`. A `:respect` or
+/// `:bleed` suffix overrides the transform's default alpha handling.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Chain(pub Vec<Step>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub transform: Transform,
+    pub alpha: Alpha,
+}
+
+impl From<Transform> for Step {
+    fn from(transform: Transform) -> Step {
+        Step {
+            transform,
+            alpha: transform.default_alpha(),
+        }
+    }
+}
+
+impl Chain {
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        let mut transformable = Transformable::new(img);
+        for step in &self.0 {
+            transformable.transform(step.transform, step.alpha);
+        }
+        transformable.into_inner()
+    }
+}
+
+struct Hex(Rgba<u8>);
+
+impl fmt::Display for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b, a] = self.0 .0;
+        if a == 0xff {
+            write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+}
+
+trait Arg: Sized {
+    fn parse(s: &str) -> Result<Self>;
+}
+
+macro_rules! from_str_arg {
+    ($($t:ty),*) => {
+        $(impl Arg for $t {
+            fn parse(s: &str) -> Result<Self> {
+                s.parse().map_err(|e| anyhow!("Invalid argument {:?}: {}", s, e))
+            }
+        })*
+    };
+}
+
+from_str_arg!(f32, f64, u8, u32, u64, i32, usize, bool);
+
+impl Arg for Rgba<u8> {
+    fn parse(s: &str) -> Result<Self> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|h| (h.len() == 6 || h.len() == 8) && h.is_ascii())
+            .ok_or_else(|| anyhow!("Invalid color {:?}, expected #rrggbb or #rrggbbaa", s))?;
+        let mut c = [0xff; 4];
+        for (i, c) in c.iter_mut().enumerate().take(hex.len() / 2) {
+            *c = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+                .map_err(|e| anyhow!("Invalid color {:?}: {}", s, e))?;
+        }
+        Ok(Rgba(c))
+    }
+}
+
+struct Args<'a> {
+    name: &'a str,
+    args: Vec<&'a str>,
+    next: usize,
+}
+
+impl<'a> Args<'a> {
+    fn next<T: Arg>(&mut self) -> Result<T> {
+        let arg = self
+            .args
+            .get(self.next)
+            .ok_or_else(|| anyhow!("{} is missing argument {}", self.name, self.next + 1))?;
+        self.next += 1;
+        T::parse(arg)
+    }
+
+    fn finish(self, t: Transform) -> Result<Transform> {
+        if self.next == self.args.len() {
+            Ok(t)
+        } else {
+            Err(anyhow!(
+                "{} takes {} arguments, got {}",
+                self.name,
+                self.next,
+                self.args.len()
+            ))
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, args) = match s.find('(') {
+            Some(open) => {
+                let inner = s[open + 1..]
+                    .strip_suffix(')')
+                    .ok_or_else(|| anyhow!("Missing ')' in {:?}", s))?;
+                let args = if inner.trim().is_empty() {
+                    vec![]
+                } else {
+                    inner.split(',').map(str::trim).collect()
+                };
+                (s[..open].trim(), args)
+            }
+            None => (s, vec![]),
+        };
+        let mut a = Args {
+            name,
+            args,
+            next: 0,
+        };
+        let t = match name {
+            "edges" => Transform::Edges(a.next()?, a.next()?, a.next()?, a.next()?),
+            "overlay_edges" => Transform::OverlayEdges(a.next()?, a.next()?, a.next()?),
+            "noise" => Transform::Noise(a.next()?, a.next()?, a.next()?),
+            "threshold" => Transform::Threshold(a.next()?, a.next()?, a.next()?),
+            "blur" => Transform::Blur(a.next()?),
+            "hue_rotate" => Transform::HueRotate(a.next()?),
+            "saturation" => Transform::Saturation(a.next()?),
+            "contrast" => Transform::Contrast(a.next()?),
+            "brightness" => Transform::Brightness(a.next()?),
+            "posterize" => Transform::Posterize(a.next()?),
+            "gradient_map" => Transform::GradientMap(a.next()?, a.next()?),
+            "palette" => Transform::Palette(a.next()?),
+            "swirl" => Transform::Swirl(a.next()?, a.next()?),
+            "wave" => Transform::Wave(a.next()?, a.next()?, a.next()?),
+            "ripple" => Transform::Ripple(a.next()?, a.next()?),
+            "fisheye" => Transform::Fisheye(a.next()?),
+            "polar" => Transform::Polar,
+            "kaleidoscope" => Transform::Kaleidoscope(a.next()?, a.next()?),
+            "tile" => Transform::Tile(a.next()?, a.next()?, a.next()?),
+            "channel_shift" => Transform::ChannelShift(a.next()?, a.next()?),
+            "scanline_jitter" => Transform::ScanlineJitter(a.next()?, a.next()?, a.next()?),
+            "pixel_sort" => Transform::PixelSort(a.next()?, a.next()?, a.next()?),
+            "block_shift" => Transform::BlockShift(a.next()?, a.next()?, a.next()?),
+            "data_bend" => Transform::DataBend(a.next()?, a.next()?),
+            _ => return Err(anyhow!("Unknown transform {:?}", name)),
+        };
+        a.finish(t)
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Transform::Edges(low, high, fg, bg) => {
+                write!(f, "edges({},{},{},{})", low, high, Hex(fg), Hex(bg))
+            }
+            Transform::OverlayEdges(low, high, fg) => {
+                write!(f, "overlay_edges({},{},{})", low, high, Hex(fg))
+            }
+            Transform::Noise(mean, stddev, seed) => {
+                write!(f, "noise({},{},{})", mean, stddev, seed)
+            }
+            Transform::Threshold(radius, fg, bg) => {
+                write!(f, "threshold({},{},{})", radius, Hex(fg), Hex(bg))
+            }
+            Transform::Blur(sigma) => write!(f, "blur({})", sigma),
+            Transform::HueRotate(degrees) => write!(f, "hue_rotate({})", degrees),
+            Transform::Saturation(factor) => write!(f, "saturation({})", factor),
+            Transform::Contrast(amount) => write!(f, "contrast({})", amount),
+            Transform::Brightness(amount) => write!(f, "brightness({})", amount),
+            Transform::Posterize(levels) => write!(f, "posterize({})", levels),
+            Transform::GradientMap(dark, light) => {
+                write!(f, "gradient_map({},{})", Hex(dark), Hex(light))
+            }
+            Transform::Palette(idx) => write!(f, "palette({})", idx),
+            Transform::Swirl(strength, radius) => write!(f, "swirl({},{})", strength, radius),
+            Transform::Wave(amplitude, wavelength, phase) => {
+                write!(f, "wave({},{},{})", amplitude, wavelength, phase)
+            }
+            Transform::Ripple(amplitude, wavelength) => {
+                write!(f, "ripple({},{})", amplitude, wavelength)
+            }
+            Transform::Fisheye(strength) => write!(f, "fisheye({})", strength),
+            Transform::Polar => write!(f, "polar"),
+            Transform::Kaleidoscope(segments, rotation) => {
+                write!(f, "kaleidoscope({},{})", segments, rotation)
+            }
+            Transform::Tile(columns, rows, mirror) => {
+                write!(f, "tile({},{},{})", columns, rows, mirror)
+            }
+            Transform::ChannelShift(offset, seed) => {
+                write!(f, "channel_shift({},{})", offset, seed)
+            }
+            Transform::ScanlineJitter(shift, chance, seed) => {
+                write!(f, "scanline_jitter({},{},{})", shift, chance, seed)
+            }
+            Transform::PixelSort(low, high, vertical) => {
+                write!(f, "pixel_sort({},{},{})", low, high, vertical)
+            }
+            Transform::BlockShift(blocks, size, seed) => {
+                write!(f, "block_shift({},{},{})", blocks, size, seed)
+            }
+            Transform::DataBend(rows, seed) => write!(f, "data_bend({},{})", rows, seed),
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (t, alpha) = match s.rsplit_once(':').map(|(t, a)| (t, a.trim())) {
+            Some((t, "respect")) => (t, Some(Alpha::Respect)),
+            Some((t, "bleed")) => (t, Some(Alpha::Bleed)),
+            Some((_, other)) => return Err(anyhow!("Unknown alpha handling {:?}", other)),
+            None => (s, None),
+        };
+        let transform: Transform = t.parse()?;
+        Ok(Step {
+            transform,
+            alpha: alpha.unwrap_or_else(|| transform.default_alpha()),
+        })
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.transform)?;
+        match self.alpha {
+            a if a == self.transform.default_alpha() => Ok(()),
+            Alpha::Respect => write!(f, ":respect"),
+            Alpha::Bleed => write!(f, ":bleed"),
+        }
+    }
+}
+
+impl FromStr for Chain {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Chain::default());
+        }
+        s.split('|')
+            .map(str::parse)
+            .collect::<Result<Vec<_>>>()
+            .map(Chain)
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, t) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", t)?;
+        }
+        Ok(())
+    }
+}
+
+// Both serialize as their DSL string, which keeps config and metadata
+// readable and gives colors their hex form.
+macro_rules! serde_via_str {
+    ($($t:ty),*) => {
+        $(impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(de::Error::custom)
+            }
+        })*
+    };
+}
+
+serde_via_str!(Transform, Chain);
@@ -0,0 +1,201 @@
+use serde_derive::Deserialize;
+
+/// How the distance between two colors is measured when picking an ink.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Summed absolute channel difference.
+    Manhattan,
+    Euclidean,
+    /// Euclidean with weights that shift with the mean red, a cheap
+    /// approximation of perceived difference.
+    Redmean,
+    Cie76,
+    Ciede2000,
+}
+
+impl Default for Metric {
+    fn default() -> Self {
+        Metric::Manhattan
+    }
+}
+
+/// Color space error diffusion accumulates in.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Space {
+    Srgb,
+    Linear,
+    Lab,
+}
+
+impl Default for Space {
+    fn default() -> Self {
+        Space::Srgb
+    }
+}
+
+fn to_linear(c: f32) -> f32 {
+    let c = c / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f32) -> f32 {
+    let c = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.max(0.0).powf(1.0 / 2.4) - 0.055
+    };
+    c * 255.0
+}
+
+// D65 reference white.
+const WHITE: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+
+/// sRGB with channels in `0..=255` to CIELAB.
+pub fn lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = [to_linear(rgb[0]), to_linear(rgb[1]), to_linear(rgb[2])];
+    let xyz = [
+        0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+        0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+        0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b,
+    ];
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let [fx, fy, fz] = [
+        f(xyz[0] / WHITE[0]),
+        f(xyz[1] / WHITE[1]),
+        f(xyz[2] / WHITE[2]),
+    ];
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIELAB back to sRGB, unclamped.
+pub fn from_lab(lab: [f32; 3]) -> [f32; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let (fx, fz) = (fy + lab[1] / 500.0, fy - lab[2] / 200.0);
+    let f = |t: f32| {
+        if t.powi(3) > 216.0 / 24389.0 {
+            t.powi(3)
+        } else {
+            (116.0 * t - 16.0) * 27.0 / 24389.0
+        }
+    };
+    let [x, y, z] = [f(fx) * WHITE[0], f(fy) * WHITE[1], f(fz) * WHITE[2]];
+    [
+        from_linear(3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z),
+        from_linear(-0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z),
+        from_linear(0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z),
+    ]
+}
+
+impl Space {
+    /// Converts sRGB with channels in `0..=255` into this space.
+    pub fn encode(self, rgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            Space::Srgb => rgb,
+            Space::Linear => [to_linear(rgb[0]), to_linear(rgb[1]), to_linear(rgb[2])],
+            Space::Lab => lab(rgb),
+        }
+    }
+
+    /// Converts back to (unclamped) sRGB.
+    pub fn decode(self, c: [f32; 3]) -> [f32; 3] {
+        match self {
+            Space::Srgb => c,
+            Space::Linear => [from_linear(c[0]), from_linear(c[1]), from_linear(c[2])],
+            Space::Lab => from_lab(c),
+        }
+    }
+}
+
+fn delta_e76(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// CIEDE2000 following Sharma, Wu and Dalal's implementation notes.
+pub fn delta_e2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    use std::f32::consts::PI;
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + 25f32.powi(7))).sqrt());
+    let (a1p, a2p) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1p, c2p) = (a1p.hypot(b1), a2p.hypot(b2));
+    let hue = |b: f32, a: f32| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).rem_euclid(2.0 * PI)
+        }
+    };
+    let (h1p, h2p) = (hue(b1, a1p), hue(b2, a2p));
+
+    let dl = l2 - l1;
+    let dc = c2p - c1p;
+    let dh = if c1p * c2p == 0.0 {
+        0.0
+    } else {
+        let d = h2p - h1p;
+        if d > PI {
+            d - 2.0 * PI
+        } else if d < -PI {
+            d + 2.0 * PI
+        } else {
+            d
+        }
+    };
+    let dh = 2.0 * (c1p * c2p).sqrt() * (dh / 2.0).sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= PI {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 2.0 * PI {
+        (h1p + h2p + 2.0 * PI) / 2.0
+    } else {
+        (h1p + h2p - 2.0 * PI) / 2.0
+    };
+    let t = 1.0 - 0.17 * (h_bar - PI / 6.0).cos()
+        + 0.24 * (2.0 * h_bar).cos()
+        + 0.32 * (3.0 * h_bar + PI / 30.0).cos()
+        - 0.20 * (4.0 * h_bar - 63.0 * PI / 180.0).cos();
+    let d_theta = PI / 6.0 * (-((h_bar * 180.0 / PI - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (c_bar_p.powi(7) / (c_bar_p.powi(7) + 25f32.powi(7))).sqrt();
+    let sl = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let sc = 1.0 + 0.045 * c_bar_p;
+    let sh = 1.0 + 0.015 * c_bar_p * t;
+    let rt = -(2.0 * d_theta).sin() * rc;
+    ((dl / sl).powi(2) + (dc / sc).powi(2) + (dh / sh).powi(2) + rt * (dc / sc) * (dh / sh)).sqrt()
+}
+
+impl Metric {
+    /// Distance between two sRGB colors with channels in `0..=255`.
+    pub fn distance(&self, a: [f32; 3], b: [f32; 3]) -> f32 {
+        let d = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+        match self {
+            Metric::Manhattan => d[0].abs() + d[1].abs() + d[2].abs(),
+            Metric::Euclidean => (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt(),
+            Metric::Redmean => {
+                let r = (a[0] + b[0]) / 2.0;
+                ((2.0 + r / 256.0) * d[0] * d[0]
+                    + 4.0 * d[1] * d[1]
+                    + (2.0 + (255.0 - r) / 256.0) * d[2] * d[2])
+                    .sqrt()
+            }
+            Metric::Cie76 => delta_e76(lab(a), lab(b)),
+            Metric::Ciede2000 => delta_e2000(lab(a), lab(b)),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::color::{Metric, Space};
+use crate::octimage::OctColorMap;
+use image::imageops::ColorMap;
+use image::{Rgb, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_derive::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    FloydSteinberg,
+    Atkinson,
+    JarvisJudiceNinke,
+    Stucki,
+    Sierra,
+    SierraLite,
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    BlueNoise,
+}
+
+/// ```toml
+/// [dither]
+/// algorithm = "atkinson"
+/// serpentine = true
+/// strength = 0.8
+/// metric = "ciede2000"
+/// space = "lab"
+/// ```
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct DitherConfig {
+    pub algorithm: Algorithm,
+    /// Alternate the scan direction every row for error diffusion.
+    pub serpentine: bool,
+    /// Scales the diffused error, or the threshold spread of ordered
+    /// dithering. 0 maps every pixel to its nearest color.
+    pub strength: f32,
+    /// Distance used to pick the nearest ink.
+    pub metric: Metric,
+    /// Space the diffused error is measured and accumulated in.
+    pub space: Space,
+}
+
+impl Default for DitherConfig {
+    fn default() -> Self {
+        DitherConfig {
+            algorithm: Algorithm::FloydSteinberg,
+            serpentine: false,
+            strength: 1.0,
+            metric: Metric::default(),
+            space: Space::default(),
+        }
+    }
+}
+
+/// Error diffusion kernels as `(dx, dy, weight)` and the weights' divisor.
+type Kernel = (&'static [(i32, i32, f32)], f32);
+
+static FLOYD_STEINBERG: Kernel = (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+
+// Only 6/8 of the error is diffused, which keeps more contrast.
+static ATKINSON: Kernel = (
+    &[
+        (1, 0, 1.0),
+        (2, 0, 1.0),
+        (-1, 1, 1.0),
+        (0, 1, 1.0),
+        (1, 1, 1.0),
+        (0, 2, 1.0),
+    ],
+    8.0,
+);
+
+static JARVIS_JUDICE_NINKE: Kernel = (
+    &[
+        (1, 0, 7.0),
+        (2, 0, 5.0),
+        (-2, 1, 3.0),
+        (-1, 1, 5.0),
+        (0, 1, 7.0),
+        (1, 1, 5.0),
+        (2, 1, 3.0),
+        (-2, 2, 1.0),
+        (-1, 2, 3.0),
+        (0, 2, 5.0),
+        (1, 2, 3.0),
+        (2, 2, 1.0),
+    ],
+    48.0,
+);
+
+static STUCKI: Kernel = (
+    &[
+        (1, 0, 8.0),
+        (2, 0, 4.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 8.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-2, 2, 1.0),
+        (-1, 2, 2.0),
+        (0, 2, 4.0),
+        (1, 2, 2.0),
+        (2, 2, 1.0),
+    ],
+    42.0,
+);
+
+static SIERRA: Kernel = (
+    &[
+        (1, 0, 5.0),
+        (2, 0, 3.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 5.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-1, 2, 2.0),
+        (0, 2, 3.0),
+        (1, 2, 2.0),
+    ],
+    32.0,
+);
+
+static SIERRA_LITE: Kernel = (&[(1, 0, 2.0), (-1, 1, 1.0), (0, 1, 1.0)], 4.0);
+
+fn clamp(c: [f32; 3]) -> Rgb<u8> {
+    Rgb([
+        c[0].round().clamp(0.0, 255.0) as u8,
+        c[1].round().clamp(0.0, 255.0) as u8,
+        c[2].round().clamp(0.0, 255.0) as u8,
+    ])
+}
+
+type Map = OctColorMap<Rgb<u8>>;
+
+fn diffuse(
+    img: &mut RgbImage,
+    map: &Map,
+    (weights, divisor): Kernel,
+    cfg: &DitherConfig,
+) -> Vec<u8> {
+    let (w, h) = (img.width() as i32, img.height() as i32);
+    let mut indices = vec![0; (w * h) as usize];
+    let space = cfg.space;
+    let mut buf: Vec<[f32; 3]> = img
+        .pixels()
+        .map(|p| space.encode([p[0] as f32, p[1] as f32, p[2] as f32]))
+        .collect();
+    for y in 0..h {
+        let reverse = cfg.serpentine && y % 2 == 1;
+        for i in 0..w {
+            let x = if reverse { w - 1 - i } else { i };
+            let old = buf[(y * w + x) as usize];
+            let index = map.index_of(&clamp(space.decode(old)));
+            indices[(y * w + x) as usize] = index as u8;
+            let new = map.color(index);
+            img.put_pixel(x as u32, y as u32, new);
+            let new = space.encode([new[0] as f32, new[1] as f32, new[2] as f32]);
+            let err = [
+                (old[0] - new[0]) * cfg.strength / divisor,
+                (old[1] - new[1]) * cfg.strength / divisor,
+                (old[2] - new[2]) * cfg.strength / divisor,
+            ];
+            for (dx, dy, weight) in weights.iter() {
+                let (nx, ny) = (if reverse { x - dx } else { x + dx }, y + dy);
+                if nx < 0 || nx >= w || ny >= h {
+                    continue;
+                }
+                let p = &mut buf[(ny * w + nx) as usize];
+                for c in 0..3 {
+                    p[c] += err[c] * weight;
+                }
+            }
+        }
+    }
+    indices
+}
+
+/// Bayer matrix of size `n` (a power of two), ranks `0..n*n`.
+fn bayer(n: usize) -> Vec<usize> {
+    let mut m = vec![0];
+    let mut size = 1;
+    while size < n {
+        let mut next = vec![0; 4 * size * size];
+        for y in 0..size {
+            for x in 0..size {
+                let v = 4 * m[y * size + x];
+                next[y * 2 * size + x] = v;
+                next[y * 2 * size + x + size] = v + 2;
+                next[(y + size) * 2 * size + x] = v + 3;
+                next[(y + size) * 2 * size + x + size] = v + 1;
+            }
+        }
+        m = next;
+        size *= 2;
+    }
+    m
+}
+
+/// Ranks `0..n*n` of a tileable blue-noise threshold map, built with
+/// Ulichney's void-and-cluster method.
+fn blue_noise(n: usize) -> Vec<usize> {
+    let len = n * n;
+    let sigma = 1.5f32;
+    // Gaussian falloff for each toroidal offset.
+    let kernel: Vec<f32> = (0..len)
+        .map(|i| {
+            let wrap = |d: usize| d.min(n - d) as f32;
+            let (dx, dy) = (wrap(i % n), wrap(i / n));
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let toggle = |energy: &mut Vec<f32>, at: usize, sign: f32| {
+        let (ax, ay) = (at % n, at / n);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let (dx, dy) = ((i % n + n - ax) % n, (i / n + n - ay) % n);
+            *e += sign * kernel[dy * n + dx];
+        }
+    };
+    let extreme = |pattern: &[bool], energy: &[f32], on: bool| -> usize {
+        let candidates = (0..len).filter(|i| pattern[*i] == on);
+        if on {
+            candidates.max_by(|a, b| energy[*a].partial_cmp(&energy[*b]).unwrap())
+        } else {
+            candidates.min_by(|a, b| energy[*a].partial_cmp(&energy[*b]).unwrap())
+        }
+        .expect("pixel")
+    };
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut pattern = vec![false; len];
+    let mut energy = vec![0.0; len];
+    let ones = len / 10;
+    while pattern.iter().filter(|p| **p).count() < ones {
+        let i = rng.gen_range(0..len);
+        if !pattern[i] {
+            pattern[i] = true;
+            toggle(&mut energy, i, 1.0);
+        }
+    }
+    // Move points from the tightest cluster to the largest void until stable.
+    loop {
+        let cluster = extreme(&pattern, &energy, true);
+        pattern[cluster] = false;
+        toggle(&mut energy, cluster, -1.0);
+        let void = extreme(&pattern, &energy, false);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; len];
+    let (initial, initial_energy) = (pattern.clone(), energy.clone());
+    for r in (0..ones).rev() {
+        let cluster = extreme(&pattern, &energy, true);
+        pattern[cluster] = false;
+        toggle(&mut energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    let (mut pattern, mut energy) = (initial, initial_energy);
+    for r in ones..len {
+        let void = extreme(&pattern, &energy, false);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+    rank
+}
+
+fn ordered(img: &mut RgbImage, map: &Map, n: usize, ranks: &[usize], strength: f32) -> Vec<u8> {
+    let spread = 128.0 * strength;
+    let len = ranks.len() as f32;
+    img.enumerate_pixels_mut()
+        .map(|(x, y, p)| {
+            let rank = ranks[(y as usize % n) * n + x as usize % n] as f32;
+            let t = ((rank + 0.5) / len - 0.5) * spread;
+            let index = map.index_of(&clamp([p[0] as f32 + t, p[1] as f32 + t, p[2] as f32 + t]));
+            *p = map.color(index);
+            index as u8
+        })
+        .collect()
+}
+
+/// Maps every pixel of `img` to a color of `map` with the configured
+/// algorithm, returning the index of each pixel's color.
+pub fn dither(img: &mut RgbImage, map: &Map, cfg: &DitherConfig) -> Vec<u8> {
+    match cfg.algorithm {
+        Algorithm::FloydSteinberg => diffuse(img, map, FLOYD_STEINBERG, cfg),
+        Algorithm::Atkinson => diffuse(img, map, ATKINSON, cfg),
+        Algorithm::JarvisJudiceNinke => diffuse(img, map, JARVIS_JUDICE_NINKE, cfg),
+        Algorithm::Stucki => diffuse(img, map, STUCKI, cfg),
+        Algorithm::Sierra => diffuse(img, map, SIERRA, cfg),
+        Algorithm::SierraLite => diffuse(img, map, SIERRA_LITE, cfg),
+        Algorithm::Bayer2 => ordered(img, map, 2, &bayer(2), cfg.strength),
+        Algorithm::Bayer4 => ordered(img, map, 4, &bayer(4), cfg.strength),
+        Algorithm::Bayer8 => ordered(img, map, 8, &bayer(8), cfg.strength),
+        Algorithm::BlueNoise => ordered(img, map, 32, &blue_noise(32), cfg.strength),
+    }
+}
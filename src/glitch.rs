@@ -0,0 +1,149 @@
+use image::{Rgba, RgbaImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn luma(p: &Rgba<u8>) -> f32 {
+    (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0
+}
+
+/// Shifts the red and blue channels by up to `max` pixels in each direction,
+/// leaving green and alpha in place.
+pub fn channel_shift(img: &mut RgbaImage, max: u32, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let max = max as i64;
+    let src = img.clone();
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    for c in &[0, 2] {
+        let (dx, dy) = (rng.gen_range(-max..=max), rng.gen_range(-max..=max));
+        for (x, y, p) in img.enumerate_pixels_mut() {
+            let sx = (x as i64 - dx).clamp(0, w - 1);
+            let sy = (y as i64 - dy).clamp(0, h - 1);
+            p[*c] = src.get_pixel(sx as u32, sy as u32)[*c];
+        }
+    }
+}
+
+/// Slides each row horizontally, wrapping around, by up to `max` pixels with
+/// the given chance per row.
+pub fn scanline_jitter(img: &mut RgbaImage, max: u32, chance: f32, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (w, h) = img.dimensions();
+    for y in 0..h {
+        if !rng.gen_bool(chance.clamp(0.0, 1.0) as f64) {
+            continue;
+        }
+        let shift = rng.gen_range(0..=max.min(w - 1)) as usize;
+        let mut row: Vec<Rgba<u8>> = (0..w).map(|x| *img.get_pixel(x, y)).collect();
+        if rng.gen() {
+            row.rotate_left(shift);
+        } else {
+            row.rotate_right(shift);
+        }
+        for (x, p) in row.into_iter().enumerate() {
+            img.put_pixel(x as u32, y, p);
+        }
+    }
+}
+
+/// Sorts runs of visible pixels whose luminance lies within `low..high` by
+/// luminance, along rows or columns.
+pub fn pixel_sort(img: &mut RgbaImage, low: f32, high: f32, vertical: bool) {
+    let (w, h) = img.dimensions();
+    let (lines, len) = if vertical { (w, h) } else { (h, w) };
+    let at = |line: u32, i: u32| if vertical { (line, i) } else { (i, line) };
+    for line in 0..lines {
+        let mut i = 0;
+        while i < len {
+            let inside = |p: &Rgba<u8>| p[3] > 0 && (low..high).contains(&luma(p));
+            let (x, y) = at(line, i);
+            if !inside(img.get_pixel(x, y)) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let mut span = vec![];
+            while i < len {
+                let (x, y) = at(line, i);
+                let p = *img.get_pixel(x, y);
+                if !inside(&p) {
+                    break;
+                }
+                span.push(p);
+                i += 1;
+            }
+            span.sort_by(|a, b| luma(a).partial_cmp(&luma(b)).unwrap());
+            for (j, p) in span.into_iter().enumerate() {
+                let (x, y) = at(line, start + j as u32);
+                img.put_pixel(x, y, p);
+            }
+        }
+    }
+}
+
+/// Copies `count` random rectangles of up to `size` pixels on a side to a
+/// random nearby offset.
+pub fn block_shift(img: &mut RgbaImage, count: u32, size: u32, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (w, h) = img.dimensions();
+    let size = size.max(2);
+    for _ in 0..count {
+        let (bw, bh) = (rng.gen_range(1..size).min(w), rng.gen_range(1..size).min(h));
+        let (x, y) = (rng.gen_range(0..=w - bw), rng.gen_range(0..=h - bh));
+        let near = |v: u32, max: u32, rng: &mut StdRng| {
+            rng.gen_range(v.saturating_sub(size)..=(v + size).min(max))
+        };
+        let (dx, dy) = (near(x, w - bw, &mut rng), near(y, h - bh, &mut rng));
+        let block = image::imageops::crop_imm(img, x, y, bw, bh).to_image();
+        image::imageops::replace(img, &block, dx, dy);
+    }
+}
+
+/// Treats the color bytes as a raw stream and corrupts a fraction of the rows
+/// the way a damaged file would: bytes dropped (misaligning the channels for
+/// the rest of the row), flipped bits, or a row repeated from above.
+pub fn data_bend(img: &mut RgbaImage, fraction: f32, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (w, h) = img.dimensions();
+    let stride = 3 * w as usize;
+    for y in 0..h {
+        if !rng.gen_bool(fraction.clamp(0.0, 1.0) as f64) {
+            continue;
+        }
+        let mut row: Vec<u8> = (0..w)
+            .flat_map(|x| {
+                let p = img.get_pixel(x, y);
+                vec![p[0], p[1], p[2]]
+            })
+            .collect();
+        match rng.gen_range(0..3) {
+            0 => {
+                let at = rng.gen_range(0..stride);
+                let drop = rng.gen_range(1..3).min(stride - at);
+                row.drain(at..at + drop);
+                row.resize(stride, 0);
+            }
+            1 => {
+                for _ in 0..rng.gen_range(1..16) {
+                    let at = rng.gen_range(0..stride);
+                    row[at] ^= 1 << rng.gen_range(0..8);
+                }
+            }
+            _ if y > 0 => {
+                let from = rng.gen_range(0..y);
+                row = (0..w)
+                    .flat_map(|x| {
+                        let p = img.get_pixel(x, from);
+                        vec![p[0], p[1], p[2]]
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+        for (x, rgb) in row.chunks(3).enumerate() {
+            let p = img.get_pixel_mut(x as u32, y);
+            p[0] = rgb[0];
+            p[1] = rgb[1];
+            p[2] = rgb[2];
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::palette::PaletteConfig;
+use crate::shapes::outline;
+use crate::variants::Variants;
+use epd_waveshare::color::OctColor;
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_derive::Deserialize;
+
+/// Effects drawn under each scattered layer. Like `[lcd]`, every enabled
+/// effect is applied, e.g.
+///
+/// ```toml
+/// [layers.shadow]
+/// offset = [4.0, 16.0]
+/// blur = [2.0, 8.0]
+/// [layers.stroke]
+/// enabled = false
+/// ```
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct LayerConfig {
+    #[serde(flatten)]
+    pub effects: Variants,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Shadow {
+    pub offset: (i32, i32),
+    pub blur: f32,
+    pub color: Rgba<u8>,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Decoration {
+    pub shadow: Option<Shadow>,
+    pub stroke: Option<(u8, Rgba<u8>)>,
+}
+
+/// Shadows and strokes are drawn in the panel inks so they dither cleanly.
+fn ink<R: Rng>(mut rng: R, palette: &PaletteConfig) -> Rgba<u8> {
+    let inks = [
+        OctColor::Black,
+        OctColor::White,
+        OctColor::Red,
+        OctColor::Green,
+        OctColor::Blue,
+        OctColor::Yellow,
+        OctColor::Orange,
+    ];
+    let [r, g, b] = palette.rgb(*inks.choose(&mut rng).expect("inks")).0;
+    Rgba([r, g, b, 0xff])
+}
+
+impl Decoration {
+    pub fn random<R>(mut rng: R, cfg: &LayerConfig, palette: &PaletteConfig) -> Decoration
+    where
+        R: Rng,
+    {
+        let fx = &cfg.effects;
+        let shadow = if fx.weight("shadow", 1) > 0 {
+            let p = fx.params("shadow");
+            let distance = p.f32(&mut rng, "offset", 4.0..16.0);
+            let angle = rng.gen_range(0.0..2.0 * std::f32::consts::PI);
+            let opacity = p.f32(&mut rng, "opacity", 0.4..0.8);
+            let mut color = ink(&mut rng, palette);
+            color[3] = (opacity * 255.0).round() as u8;
+            Some(Shadow {
+                offset: (
+                    (distance * angle.cos()).round() as i32,
+                    (distance * angle.sin()).round() as i32,
+                ),
+                blur: p.f32(&mut rng, "blur", 2.0..8.0),
+                color,
+            })
+        } else {
+            None
+        };
+        let stroke = if fx.weight("stroke", 1) > 0 {
+            let p = fx.params("stroke");
+            let width = p.u32(&mut rng, "width", 2..6).min(u8::MAX as u32) as u8;
+            Some((width, ink(&mut rng, palette)))
+        } else {
+            None
+        };
+        Decoration { shadow, stroke }
+    }
+
+    /// Draws the shadow and stroke of `layer` into `base`, ready for `layer`
+    /// itself to be composited on top. Both images are the same size.
+    pub fn draw(&self, base: &mut RgbaImage, layer: &RgbaImage) {
+        let (w, h) = layer.dimensions();
+        if let Some(shadow) = self.shadow {
+            let (dx, dy) = shadow.offset;
+            let alpha = GrayImage::from_fn(w, h, |x, y| {
+                let (sx, sy) = (x as i64 - dx as i64, y as i64 - dy as i64);
+                if sx < 0 || sy < 0 || sx >= w as i64 || sy >= h as i64 {
+                    Luma([0])
+                } else {
+                    Luma([layer.get_pixel(sx as u32, sy as u32)[3]])
+                }
+            });
+            let alpha = if shadow.blur > 0.0 {
+                imageproc::filter::gaussian_blur_f32(&alpha, shadow.blur)
+            } else {
+                alpha
+            };
+            let c = shadow.color;
+            let shadow = RgbaImage::from_fn(w, h, |x, y| {
+                let a = alpha.get_pixel(x, y)[0] as u32 * c[3] as u32 / 255;
+                Rgba([c[0], c[1], c[2], a as u8])
+            });
+            image::imageops::overlay(base, &shadow, 0, 0);
+        }
+        if let Some((width, color)) = self.stroke {
+            // Only around the layer, under it the stroke would show through
+            // any blend mode other than normal.
+            let stroke = outline(layer, width);
+            base.pixels_mut()
+                .zip(stroke.pixels())
+                .zip(layer.pixels())
+                .filter(|((_, s), l)| s[0] != 0 && l[3] == 0)
+                .for_each(|((p, _), _)| *p = color);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::variants::Variants;
+use image::{Rgba, RgbaImage};
+use rand::Rng;
+use serde_derive::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Model {
+    Dmg,
+    Pocket,
+    Cgb,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LcdEffect {
+    Tint(Model),
+    Ghosting(f32),
+    Grid(f32),
+    Glare(f32, f32),
+}
+
+/// Unlike the other menus every enabled effect is applied, in the order
+/// ghosting, tint, grid, glare, e.g.
+///
+/// ```toml
+/// [lcd]
+/// scale = 4
+/// [lcd.glare]
+/// enabled = false
+/// [lcd.grid]
+/// darkness = [0.2, 0.5]
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LcdConfig {
+    /// Size in pixels each Game Boy pixel is blown up to for the grid.
+    pub scale: u32,
+    #[serde(flatten)]
+    pub effects: Variants,
+}
+
+impl Default for LcdConfig {
+    fn default() -> Self {
+        LcdConfig {
+            scale: 4,
+            effects: Variants::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Lcd {
+    scale: u32,
+    effects: Vec<LcdEffect>,
+}
+
+fn lerp(dark: [f32; 3], light: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        dark[0] + (light[0] - dark[0]) * t,
+        dark[1] + (light[1] - dark[1]) * t,
+        dark[2] + (light[2] - dark[2]) * t,
+    ]
+}
+
+fn tint(model: Model, p: &Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b] = [p[0] as f32, p[1] as f32, p[2] as f32];
+    let luma = (0.299 * r + 0.587 * g + 0.114 * b) / 255.0;
+    let out = match model {
+        Model::Dmg => lerp([15.0, 56.0, 15.0], [155.0, 188.0, 15.0], luma),
+        Model::Pocket => lerp([43.0, 43.0, 38.0], [197.0, 202.0, 164.0], luma),
+        // Washed out colors of the CGB's LCD, as approximated by Gambatte.
+        Model::Cgb => [
+            (13.0 * r + 2.0 * g + b) / 16.0,
+            (3.0 * g + b) / 4.0,
+            (3.0 * r + 2.0 * g + 11.0 * b) / 16.0,
+        ],
+    };
+    Rgba([
+        out[0].round() as u8,
+        out[1].round() as u8,
+        out[2].round() as u8,
+        p[3],
+    ])
+}
+
+impl Lcd {
+    pub fn random<R>(mut rng: R, cfg: &LcdConfig, model: Model) -> Lcd
+    where
+        R: Rng,
+    {
+        let fx = &cfg.effects;
+        let mut effects = vec![];
+        if fx.weight("ghosting", 1) > 0 {
+            let p = fx.params("ghosting");
+            effects.push(LcdEffect::Ghosting(p.f32(&mut rng, "strength", 0.1..0.4)));
+        }
+        if fx.weight("tint", 1) > 0 {
+            effects.push(LcdEffect::Tint(model));
+        }
+        if fx.weight("grid", 1) > 0 {
+            let p = fx.params("grid");
+            effects.push(LcdEffect::Grid(p.f32(&mut rng, "darkness", 0.2..0.5)));
+        }
+        if fx.weight("glare", 1) > 0 {
+            let p = fx.params("glare");
+            effects.push(LcdEffect::Glare(
+                p.f32(&mut rng, "strength", 0.05..0.2),
+                rng.gen_range(0.0..2.0 * std::f32::consts::PI),
+            ));
+        }
+        Lcd {
+            scale: cfg.scale.max(1),
+            effects,
+        }
+    }
+
+    /// Applies the effects to `frame`, where `previous` holds the frames
+    /// captured before it, oldest first, for ghosting. The result is blown up
+    /// by the configured scale.
+    pub fn apply(&self, frame: &RgbaImage, previous: &[RgbaImage]) -> RgbaImage {
+        let (native, scaled): (Vec<LcdEffect>, Vec<LcdEffect>) = self
+            .effects
+            .iter()
+            .partition(|e| matches!(e, LcdEffect::Ghosting(_) | LcdEffect::Tint(_)));
+        let mut img = frame.clone();
+        for effect in native {
+            self.effect(&mut img, effect, previous);
+        }
+        let mut img = image::imageops::resize(
+            &img,
+            img.width() * self.scale,
+            img.height() * self.scale,
+            image::imageops::FilterType::Nearest,
+        );
+        for effect in scaled {
+            self.effect(&mut img, effect, previous);
+        }
+        img
+    }
+
+    fn effect(&self, img: &mut RgbaImage, effect: LcdEffect, previous: &[RgbaImage]) {
+        match effect {
+            LcdEffect::Ghosting(strength) => {
+                // Each older frame fades by another factor of `strength`.
+                let mut weight = strength;
+                let dim = img.dimensions();
+                for prev in previous.iter().rev().filter(|p| p.dimensions() == dim) {
+                    img.pixels_mut().zip(prev.pixels()).for_each(|(p, q)| {
+                        for c in 0..3 {
+                            p[c] =
+                                (p[c] as f32 * (1.0 - weight) + q[c] as f32 * weight).round() as u8;
+                        }
+                    });
+                    weight *= strength;
+                }
+            }
+            LcdEffect::Tint(model) => img.pixels_mut().for_each(|p| *p = tint(model, p)),
+            LcdEffect::Grid(darkness) => {
+                let scale = self.scale;
+                if scale > 1 {
+                    img.enumerate_pixels_mut()
+                        .filter(|(x, y, _)| x % scale == scale - 1 || y % scale == scale - 1)
+                        .for_each(|(_, _, p)| {
+                            for c in 0..3 {
+                                p[c] = (p[c] as f32 * (1.0 - darkness)).round() as u8;
+                            }
+                        });
+                }
+            }
+            LcdEffect::Glare(strength, angle) => {
+                let (w, h) = (img.width() as f32, img.height() as f32);
+                let (sin, cos) = angle.sin_cos();
+                let extent = (w * cos).abs() + (h * sin).abs();
+                img.enumerate_pixels_mut().for_each(|(x, y, p)| {
+                    let d = (x as f32 - w / 2.0) * cos + (y as f32 - h / 2.0) * sin;
+                    let t = strength * (0.5 - d / extent).clamp(0.0, 1.0);
+                    for c in 0..3 {
+                        p[c] = (p[c] as f32 + (255.0 - p[c] as f32) * t).round() as u8;
+                    }
+                });
+            }
+        }
+    }
+}
@@ -1,6 +1,22 @@
+mod background;
+mod blend;
+mod caption;
+mod chain;
+mod color;
+mod dither;
+mod glitch;
+mod layer;
+mod lcd;
+mod metrics;
+mod palette;
 mod qr;
 mod roms;
+mod screen;
+mod shapes;
+mod text;
+mod tone;
 mod transform;
+mod variants;
 use embedded_graphics::{
     geometry::Size, prelude::*, primitives::PrimitiveStyleBuilder, primitives::Rectangle,
 };
@@ -10,7 +26,11 @@ use epd_waveshare::graphics::OctDisplay;
 use epd_waveshare::{epd5in65f::*, prelude::*};
 use rand::seq::SliceRandom;
 mod octimage;
+mod pixelart;
 use anyhow::{anyhow, Result};
+use background::Background;
+use blend::Blend;
+use chain::Chain;
 use display::create;
 use image::imageops::FilterType;
 use image::io::Reader as ImageReader;
@@ -18,12 +38,16 @@ use image::DynamicImage;
 use image::GenericImageView;
 use image::ImageBuffer;
 use imageproc::geometric_transformations::*;
+use layer::Decoration;
+use lcd::Lcd;
 use octimage::OctDither;
+use pixelart::ScalingConfig;
 use qr::QrCode;
 use rand::Rng;
-use roms::{get_frames, Config, GameboyImage};
-use std::path::PathBuf;
-use transform::{Transform, Transformable};
+use roms::{get_frames, CartridgeImage, Config, GameboyImage};
+use shapes::{Frame, Shape};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
 
 static COLORS: [OctColor; 8] = [
     OctColor::HiZ,
@@ -105,28 +129,88 @@ where
 fn place(
     img: &GameboyImage,
     screen: &ImageBuffer<image::Rgba<u8>, Vec<u8>>,
-) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+    scaling: &ScalingConfig,
+    fit: Option<(u32, u32)>,
+) -> Option<ImageBuffer<image::Rgba<u8>, Vec<u8>>> {
+    let quad = img.screen.expect("Screen detected before placing frames");
+    warp_onto(&img.path, quad, screen, scaling, fit)
+}
+
+/// Warps `content` onto the `quad` of the image at `path`, `None` if the
+/// quad is degenerate. With `fit` the image is first scaled to fit within
+/// that size, so `content` is only resampled once, by `scaling`.
+fn warp_onto(
+    path: &Path,
+    quad: screen::Quad,
+    content: &ImageBuffer<image::Rgba<u8>, Vec<u8>>,
+    scaling: &ScalingConfig,
+    fit: Option<(u32, u32)>,
+) -> Option<ImageBuffer<image::Rgba<u8>, Vec<u8>>> {
     use imageproc::geometric_transformations::*;
-    let mut gb = ImageReader::open(&img.path)
+    let mut gb = ImageReader::open(path)
         .unwrap()
         .decode()
         .unwrap()
         .to_rgba8();
+    let quad = match fit {
+        Some((w, h)) => {
+            let (gw, gh) = gb.dimensions();
+            let ratio = (w as f32 / gw as f32).min(h as f32 / gh as f32);
+            let (nw, nh) = (
+                ((gw as f32 * ratio).round() as u32).max(1),
+                ((gh as f32 * ratio).round() as u32).max(1),
+            );
+            if (nw, nh) != (gw, gh) {
+                gb = image::imageops::resize(&gb, nw, nh, FilterType::Gaussian);
+            }
+            let (sx, sy) = (nw as f32 / gw as f32, nh as f32 / gh as f32);
+            quad.map(|(x, y)| (x * sx, y * sy))
+        }
+        None => quad,
+    };
+    let screen = if scaling.pixel_art() {
+        let side =
+            |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+        let target = (
+            side(quad[0], quad[1]).max(side(quad[3], quad[2])).ceil() as u32,
+            side(quad[0], quad[3]).max(side(quad[1], quad[2])).ceil() as u32,
+        );
+        scaling.upscale(content, target)
+    } else {
+        content.clone()
+    };
     let (x, y) = screen.dimensions();
     let (x, y) = (x as f32, y as f32);
     let dim = [(0.0, 0.0), (x, 0.0), (x, y), (0.0, y)];
-    let proj = Projection::from_control_points(dim, img.screen).unwrap();
-    let screen = DynamicImage::ImageRgba8(screen.clone()).to_rgba8();
+    let proj = Projection::from_control_points(dim, quad)?;
     let mut gb_scratch = gb.clone();
-    warp_into(
-        &screen,
-        &proj,
-        Interpolation::Bicubic,
-        image::Rgba([0, 0, 0, 0]),
-        &mut gb_scratch,
-    );
+    scaling.warp_into(&screen, &proj, &mut gb_scratch);
     image::imageops::overlay(&mut gb, &gb_scratch, 0, 0);
-    gb
+    Some(gb)
+}
+
+/// Crops `boxart` to the aspect ratio of the cartridge's label, keeping the
+/// top where the title usually is, and warps it onto the label of the
+/// cartridge scaled to fit the panel.
+fn cartridge(cart: &CartridgeImage, boxart: &DynamicImage) -> Option<image::RgbaImage> {
+    let q = cart.label;
+    let side = |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let aspect = (side(q[0], q[1]) + side(q[3], q[2])) / (side(q[0], q[3]) + side(q[1], q[2]));
+    let (w, h) = boxart.dimensions();
+    let (cw, ch) = if w as f32 / h as f32 > aspect {
+        ((h as f32 * aspect).round() as u32, h)
+    } else {
+        (w, (w as f32 / aspect).round() as u32)
+    };
+    let label = boxart.crop_imm((w - cw) / 2, 0, cw, ch).to_rgba8();
+    // Boxart isn't pixel art, so always warp it smoothly.
+    warp_onto(
+        &cart.path,
+        q,
+        &label,
+        &ScalingConfig::default(),
+        Some((HEIGHT, WIDTH)),
+    )
 }
 
 use std::marker::PhantomData;
@@ -232,7 +316,277 @@ async fn rocket() {
         .await;
 }
 
+#[derive(StructOpt)]
+#[structopt(name = "slate", about = "Gameboy randomized e-paper image display")]
+struct Opt {
+    #[structopt(long, parse(from_os_str), default_value = "assets.toml")]
+    config: PathBuf,
+    /// Transform chain applied to every image instead of random ones, e.g.
+    /// "blur(3.2) | posterize(4)"
+    #[structopt(long)]
+    chain: Option<Chain>,
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Find the screen corners in Game Boy shell images
+    Detect {
+        /// One of auto, transparent, lcd or contour
+        #[structopt(long, default_value = "auto")]
+        method: screen::Method,
+        #[structopt(parse(from_os_str))]
+        images: Vec<PathBuf>,
+    },
+    /// Run a transform chain on an image and write the result
+    Apply {
+        /// Transform chain, e.g. "blur(3.2) | posterize(4)"
+        chain: Chain,
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// Scatter the result onto a panel sized canvas using the random
+        /// projection generated from this seed
+        #[structopt(long)]
+        project: Option<u64>,
+        /// Also write the panel preview here, dithered with the tone, palette
+        /// and dither settings from the config
+        #[structopt(long, parse(from_os_str))]
+        dither: Option<PathBuf>,
+    },
+    /// Dither an image with the configured tone, palette and dither
+    /// settings and report how faithful the result is
+    Dither {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// Write the input, the result and their difference side by side
+        #[structopt(long, parse(from_os_str))]
+        compare: Option<PathBuf>,
+        /// Blur in pixels approximating the viewing distance
+        #[structopt(long, default_value = "1.5")]
+        blur: f32,
+    },
+    /// Show a `.epd` panel buffer saved by an earlier run
+    Send {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+    /// Click the screen corners of a Game Boy shell image in the simulator
+    #[cfg(all(feature = "sim", not(feature = "spi")))]
+    Calibrate {
+        /// Rom to take the preview frame from instead of a test pattern
+        #[structopt(long, parse(from_os_str))]
+        rom: Option<PathBuf>,
+        #[structopt(long)]
+        color: bool,
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+    },
+}
+
+fn snippet(path: &Path, quad: &screen::Quad, color: Option<bool>) -> String {
+    let mut s = String::from("[[gameboy]]\n");
+    if let Some(color) = color {
+        s += &format!("color = {}\n", color);
+    }
+    s += &format!("path = {:?}\n", path.display().to_string());
+    s += &format!(
+        "screen = [{}]\n",
+        quad.iter()
+            .map(|(x, y)| format!("[{}, {}]", x.round(), y.round()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    s
+}
+
+fn detect(method: screen::Method, images: &[PathBuf]) -> Result<()> {
+    for path in images {
+        let img = ImageReader::open(path)?.decode()?.to_rgba8();
+        match screen::detect(&img, method) {
+            Some(quad) => println!("{}", snippet(path, &quad, None)),
+            None => eprintln!("No screen found in {}", path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Transparent areas show as the white the panel is cleared to.
+fn on_white(img: &DynamicImage) -> image::RgbaImage {
+    let mut base = image::RgbaImage::from_pixel(
+        img.width(),
+        img.height(),
+        image::Rgba([0xff, 0xff, 0xff, 0xff]),
+    );
+    image::imageops::overlay(&mut base, img, 0, 0);
+    base
+}
+
+fn apply(
+    config: &Path,
+    chain: &Chain,
+    input: &Path,
+    output: &Path,
+    project: Option<u64>,
+    dither: Option<&Path>,
+) -> Result<()> {
+    use rand::SeedableRng;
+    let cfg = Config::load(config)?;
+    let img = chain.apply(ImageReader::open(input)?.decode()?);
+    let img = match project {
+        Some(seed) => {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let projection = transform::projection(&mut rng, img.dimensions(), (HEIGHT, WIDTH));
+            let mut canvas = image::RgbaImage::new(HEIGHT, WIDTH);
+            cfg.scaling
+                .warp_into(&img.into_rgba8(), &projection, &mut canvas);
+            DynamicImage::ImageRgba8(canvas)
+        }
+        None => img,
+    };
+    img.save(output)?;
+    if let Some(dither) = dither {
+        let mut base = on_white(&img);
+        if let Some(tone) = &cfg.tone {
+            tone.apply(&mut base);
+        }
+        OctDither::calibrated(
+            DynamicImage::ImageRgba8(base),
+            Point::zero(),
+            &cfg.palette,
+            &cfg.dither,
+        )
+        .output()
+        .save(dither)?;
+    }
+    Ok(())
+}
+
+fn dither_image(
+    config: &Path,
+    input: &Path,
+    output: &Path,
+    compare: Option<&Path>,
+    blur: f32,
+) -> Result<()> {
+    let cfg = Config::load(config)?;
+    let mut base = on_white(&ImageReader::open(input)?.decode()?);
+    if let Some(tone) = &cfg.tone {
+        tone.apply(&mut base);
+    }
+    let reference = DynamicImage::ImageRgba8(base).into_rgb8();
+    let dither = OctDither::calibrated(
+        DynamicImage::ImageRgb8(reference.clone()),
+        Point::zero(),
+        &cfg.palette,
+        &cfg.dither,
+    );
+    dither.output().save(output)?;
+    let rendered = dither.render();
+    println!("{}", metrics::measure(&reference, &rendered, blur));
+    if let Some(compare) = compare {
+        metrics::side_by_side(&reference, &rendered, blur).save(compare)?;
+    }
+    Ok(())
+}
+
+/// Four shades of DMG green with a red top left corner, so a mirrored or
+/// rotated calibration is obvious in the preview.
+#[cfg(all(feature = "sim", not(feature = "spi")))]
+fn test_frame() -> image::RgbaImage {
+    const SHADES: [[u8; 4]; 4] = [
+        [0x9b, 0xbc, 0x0f, 0xff],
+        [0x8b, 0xac, 0x0f, 0xff],
+        [0x30, 0x62, 0x30, 0xff],
+        [0x0f, 0x38, 0x0f, 0xff],
+    ];
+    image::RgbaImage::from_fn(160, 144, |x, y| {
+        if x < 16 && y < 16 {
+            image::Rgba([0xff, 0, 0, 0xff])
+        } else {
+            image::Rgba(SHADES[((x / 8 + y / 8) % 4) as usize])
+        }
+    })
+}
+
+#[cfg(all(feature = "sim", not(feature = "spi")))]
+fn calibrate(image: &Path, rom: Option<&Path>, color: bool) -> Result<()> {
+    let shell = ImageReader::open(image)?.decode()?.to_rgba8();
+    let frame = match rom {
+        Some(rom) => get_frames(rom, None, &[600])?
+            .pop()
+            .ok_or_else(|| anyhow!("No frame from {}", rom.display()))?,
+        None => test_frame(),
+    };
+    let quad = display::calibrate(&shell, |screen| {
+        let gb = GameboyImage {
+            screen: Some(screen),
+            path: image.to_owned(),
+            color,
+            model: None,
+        };
+        place(&gb, &frame, &ScalingConfig::default(), None)
+    })
+    .ok_or_else(|| anyhow!("Calibration aborted"))?;
+    println!("{}", snippet(image, &quad, Some(color)));
+    Ok(())
+}
+
 fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    match &opt.cmd {
+        Some(Command::Detect { method, images }) => detect(*method, images),
+        Some(Command::Apply {
+            chain,
+            input,
+            output,
+            project,
+            dither,
+        }) => apply(
+            &opt.config,
+            chain,
+            input,
+            output,
+            *project,
+            dither.as_deref(),
+        ),
+        Some(Command::Dither {
+            input,
+            output,
+            compare,
+            blur,
+        }) => dither_image(&opt.config, input, output, compare.as_deref(), *blur),
+        Some(Command::Send { path }) => send(path),
+        #[cfg(all(feature = "sim", not(feature = "spi")))]
+        Some(Command::Calibrate { rom, color, image }) => calibrate(image, rom.as_deref(), *color),
+        None => run(&opt.config, opt.chain.as_ref()),
+    }
+}
+
+fn send(path: &Path) -> Result<()> {
+    let buffer = std::fs::read(path)?;
+    let len = WIDTH as usize / 2 * HEIGHT as usize;
+    if buffer.len() != len {
+        return Err(anyhow!(
+            "{} is {} bytes, a panel buffer is {}",
+            path.display(),
+            buffer.len(),
+            len
+        ));
+    }
+    let (spi, delay, epd) = create();
+    let mut controller = Controller::new(epd, spi, delay)?;
+    controller.draw(|display| {
+        display.get_mut_buffer().copy_from_slice(&buffer);
+        Ok(())
+    })
+}
+
+fn run(toml_path: &Path, chain: Option<&Chain>) -> Result<()> {
     let path = PathBuf::from("gameboy");
     if !path.exists() {
         std::fs::create_dir(&path).expect("Directory created");
@@ -248,8 +602,9 @@ fn main() -> Result<()> {
     });
 
     println!("Roms searching!");
-    let toml_path = PathBuf::from("assets.toml");
-    let cfg: Config = toml::from_str(&std::fs::read_to_string(&toml_path).unwrap()).unwrap();
+    let mut cfg = Config::load(toml_path)?;
+    cfg.detect_screens()?;
+    let face = cfg.caption.as_ref().map(|c| c.face()).transpose()?;
 
     let roms = cfg
         .romdata
@@ -297,64 +652,92 @@ fn main() -> Result<()> {
         controller.draw(|display| {
             display.set_rotation(DisplayRotation::Rotate270);
             let mut base = DynamicImage::new_rgba8(HEIGHT, WIDTH);
-            let bg = if rng.gen() {
-                image::imageops::vertical_gradient
-            } else {
-                image::imageops::horizontal_gradient
-            };
-
-            let start = transform::rgba(&mut rng, Some(0xff));
-            let end = transform::rgba(&mut rng, Some(0xff));
-            bg(&mut base, &start, &end);
+            let tiles = background::tiles(&frames);
+            let bg = Background::random(
+                &mut rng,
+                &cfg.background,
+                (HEIGHT, WIDTH),
+                !tiles.is_empty(),
+            );
+            // What went into the image, bottom layer first.
+            let mut notes = vec![format!("background: {:?}", bg)];
+            bg.render(base.as_mut_rgba8().unwrap(), &tiles);
 
+            let boxart = rom
+                .boxart
+                .as_ref()
+                .map(|boxart| -> Result<DynamicImage> {
+                    ImageReader::new(std::io::Cursor::new(std::fs::read(boxart).unwrap()))
+                        .with_guessed_format()
+                        .map_err(|e| anyhow!("{}", e))?
+                        .decode()
+                        .map_err(|e| anyhow!("{}", e))
+                })
+                .transpose()
+                .ok()
+                .flatten();
+            let cart = boxart
+                .as_ref()
+                .zip(cfg.cartridge.choose(&mut rng))
+                .and_then(|(boxart, cart)| cartridge(cart, boxart))
+                .map(DynamicImage::ImageRgba8);
             let mut images = frames
                 .iter()
-                .map(|f| {
-                    let f = f.clone();
-                    let img = place(&cfg.gameboy[rng.gen_range(0..cfg.gameboy.len())], &f);
-                    let img = DynamicImage::ImageRgba8(img);
-                    img.resize(HEIGHT, WIDTH, FilterType::Gaussian)
+                .enumerate()
+                .filter_map(|(i, f)| {
+                    let gb = &cfg.gameboy[rng.gen_range(0..cfg.gameboy.len())];
+                    let f = match &cfg.lcd {
+                        Some(lcd) => Lcd::random(&mut rng, lcd, gb.model()).apply(f, &frames[..i]),
+                        None => f.clone(),
+                    };
+                    let img = place(gb, &f, &cfg.scaling, Some((HEIGHT, WIDTH)))?;
+                    Some(DynamicImage::ImageRgba8(img))
                 })
-                .chain(
-                    rom.boxart
-                        .as_ref()
-                        .map(|boxart| -> Result<DynamicImage> {
-                            ImageReader::new(std::io::Cursor::new(std::fs::read(boxart).unwrap()))
-                                .with_guessed_format()
-                                .map_err(|e| anyhow!("{}", e))?
-                                .decode()
-                                .map_err(|e| anyhow!("{}", e))
-                        })
-                        .transpose()
-                        .ok()
-                        .flatten()
-                        .into_iter(),
-                )
+                .chain(boxart.into_iter())
+                .chain(cart.into_iter())
                 .collect::<Vec<_>>();
 
             images.shuffle(&mut rng);
             for img in images.into_iter() {
-                let transforms = (0..rng.gen_range(1..10))
-                    .map(|_| Transform::random(&mut rng, HEIGHT, WIDTH))
-                    .collect::<Vec<_>>();
-
-                let mut transformable = Transformable::new(img);
-                for t in transforms {
-                    transformable.transform(t);
-                }
-                let img = transformable.into_inner();
+                let transforms = match chain {
+                    Some(chain) => chain.clone(),
+                    None => cfg.transforms.chain(&mut rng, HEIGHT, WIDTH),
+                };
+                let img = transforms.apply(img);
+                notes.push(format!("chain: {}", transforms));
+                let mut img = img.into_rgba8();
+                let (shape, frame) = (
+                    Shape::random(&mut rng, &cfg.shapes),
+                    Frame::random(&mut rng, &cfg.frames),
+                );
+                notes.push(format!("shape: {:?}", shape));
+                notes.push(format!("frame: {:?}", frame));
+                shape.apply(&mut img);
+                let img = DynamicImage::ImageRgba8(frame.apply(&img));
                 let projection = transform::projection(&mut rng, img.dimensions(), (HEIGHT, WIDTH));
-                use image::Rgba;
                 let mut scratch = base.clone();
-                imageproc::geometric_transformations::warp_into(
+                cfg.scaling.warp_into(
                     &img.into_rgba8(),
                     &projection,
-                    Interpolation::Bicubic,
-                    Rgba([0, 0, 0, 0]),
                     scratch.as_mut_rgba8().unwrap(),
                 );
-                image::imageops::overlay(&mut base, &scratch, 0, 0);
+                if let Some(layers) = &cfg.layers {
+                    let decoration = Decoration::random(&mut rng, layers, &cfg.palette);
+                    notes.push(format!("decoration: {:?}", decoration));
+                    decoration.draw(base.as_mut_rgba8().unwrap(), scratch.as_rgba8().unwrap());
+                }
+                let blend = Blend::random(&mut rng, &cfg.blend);
+                notes.push(format!("blend: {:?}", blend));
+                blend.composite(base.as_mut_rgba8().unwrap(), scratch.as_rgba8().unwrap());
             }
+            if let Some(tone) = &cfg.tone {
+                tone.apply(base.as_mut_rgba8().unwrap());
+            }
+            let mut overlay = image::RgbaImage::new(HEIGHT, WIDTH);
+            if let Some((caption, face)) = cfg.caption.as_ref().zip(face.as_ref()) {
+                caption.draw(face, &mut overlay, rom, &cfg.palette);
+            }
+            image::imageops::overlay(&mut base, &overlay, 0, 0);
             use sha2::Digest;
             let mut sha = sha2::Sha256::new();
             sha.update(base.as_bytes());
@@ -369,23 +752,36 @@ fn main() -> Result<()> {
             );
             println!("Target URL {}", uri);
 
-            let dither = OctDither::new_default(base, Point::zero());
+            let mut dither = OctDither::calibrated(base, Point::zero(), &cfg.palette, &cfg.dither);
+            dither.stamp(&overlay);
             let image = dither.output();
             use std::os::unix::fs::symlink;
             image.save(&output)?;
+            // The `chain:` lines can be passed to `--chain` to reproduce a layer.
+            std::fs::write(
+                output.with_extension("txt"),
+                notes.iter().map(|n| format!("{}\n", n)).collect::<String>(),
+            )?;
             let symlink_file = path.join("latest.png");
             std::fs::remove_file(&symlink_file)?;
             symlink(&png_name, &symlink_file)?;
-            dither.iter().draw(display).unwrap();
-            let code = QrCode::new(
+            let rotation = display.rotation();
+            dither.pack(display.get_mut_buffer(), (WIDTH, HEIGHT), rotation);
+            let mut code = QrCode::new(
                 Point::new(0, 0),
                 2,
                 OctColor::Black,
                 OctColor::White,
                 uri.as_bytes(),
             );
+            // Keep the code off a caption band along the top.
+            if let Some(caption::Position::Top) = cfg.caption.as_ref().map(|c| c.position) {
+                code.translate_mut(Point::new(0, (WIDTH - code.size().height) as i32));
+            }
 
             Drawable::draw(&code, display).unwrap();
+            // The exact panel buffer, for `send` to show again later.
+            std::fs::write(output.with_extension("epd"), display.buffer())?;
             Ok(())
         })?;
         controller.delay.delay_ms(30 * 60_000u32);
@@ -0,0 +1,158 @@
+use crate::color::{delta_e2000, lab, Space};
+use image::{Rgb, RgbImage};
+use std::fmt;
+
+/// How closely a dithered image reproduces the image it was dithered from.
+/// Both are blurred first, as the dots blend together at viewing distance.
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics {
+    /// Peak signal to noise ratio in dB over the RGB channels, `None` when
+    /// the images match exactly.
+    pub psnr: Option<f32>,
+    /// Mean structural similarity of the luma, 1 for identical images.
+    pub ssim: f32,
+    /// CIEDE2000 difference.
+    pub delta_e_mean: f32,
+    pub delta_e_max: f32,
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.psnr {
+            Some(psnr) => write!(f, "PSNR {:.2} dB", psnr)?,
+            None => write!(f, "PSNR identical")?,
+        }
+        write!(
+            f,
+            ", SSIM {:.4}, ΔE mean {:.2} max {:.2}",
+            self.ssim, self.delta_e_mean, self.delta_e_max
+        )
+    }
+}
+
+/// Separable gaussian blur of a single channel, clamped at the edges.
+fn blur(plane: &[f32], (w, h): (usize, usize), sigma: f32) -> Vec<f32> {
+    if sigma <= 0.0 {
+        return plane.to_vec();
+    }
+    let radius = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|d| (-(d * d) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    let pass = |src: &[f32], horizontal: bool| -> Vec<f32> {
+        (0..w * h)
+            .map(|i| {
+                let (x, y) = ((i % w) as i64, (i / w) as i64);
+                kernel
+                    .iter()
+                    .zip(-radius..=radius)
+                    .map(|(k, d)| {
+                        let (sx, sy) = if horizontal {
+                            ((x + d).clamp(0, w as i64 - 1), y)
+                        } else {
+                            (x, (y + d).clamp(0, h as i64 - 1))
+                        };
+                        k * src[sy as usize * w + sx as usize]
+                    })
+                    .sum::<f32>()
+                    / total
+            })
+            .collect()
+    };
+    pass(&pass(plane, true), false)
+}
+
+fn psnr(a: &[[f32; 3]], b: &[[f32; 3]]) -> Option<f32> {
+    let sum: f32 = a
+        .iter()
+        .flatten()
+        .zip(b.iter().flatten())
+        .map(|(a, b)| (a.clamp(0.0, 255.0) - b.clamp(0.0, 255.0)).powi(2))
+        .sum();
+    let mse = sum / (3 * a.len()) as f32;
+    if mse == 0.0 {
+        None
+    } else {
+        Some(10.0 * (255.0 * 255.0 / mse).log10())
+    }
+}
+
+fn ssim(a: &[[f32; 3]], b: &[[f32; 3]], size: (usize, usize)) -> f32 {
+    let luma = |img: &[[f32; 3]]| -> Vec<f32> {
+        img.iter()
+            .map(|p| 0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2])
+            .collect()
+    };
+    let (x, y) = (luma(a), luma(b));
+    let window = |v: Vec<f32>| blur(&v, size, 1.5);
+    let (mx, my) = (window(x.clone()), window(y.clone()));
+    let xx = window(x.iter().map(|v| v * v).collect());
+    let yy = window(y.iter().map(|v| v * v).collect());
+    let xy = window(x.iter().zip(y.iter()).map(|(x, y)| x * y).collect());
+    let (c1, c2) = ((0.01f32 * 255.0).powi(2), (0.03f32 * 255.0).powi(2));
+    let sum: f32 = (0..x.len())
+        .map(|i| {
+            let (sx, sy) = (xx[i] - mx[i] * mx[i], yy[i] - my[i] * my[i]);
+            let sxy = xy[i] - mx[i] * my[i];
+            (2.0 * mx[i] * my[i] + c1) * (2.0 * sxy + c2)
+                / ((mx[i] * mx[i] + my[i] * my[i] + c1) * (sx + sy + c2))
+        })
+        .sum();
+    sum / x.len() as f32
+}
+
+/// Blurs in linear light, where the eye mixes neighbouring dots.
+fn blur_rgb(img: &RgbImage, sigma: f32) -> Vec<[f32; 3]> {
+    let size = (img.width() as usize, img.height() as usize);
+    let linear: Vec<[f32; 3]> = img
+        .pixels()
+        .map(|p| Space::Linear.encode([p[0] as f32, p[1] as f32, p[2] as f32]))
+        .collect();
+    let planes: Vec<Vec<f32>> = (0..3)
+        .map(|c| {
+            let plane: Vec<f32> = linear.iter().map(|p| p[c]).collect();
+            blur(&plane, size, sigma)
+        })
+        .collect();
+    (0..linear.len())
+        .map(|i| Space::Linear.decode([planes[0][i], planes[1][i], planes[2][i]]))
+        .collect()
+}
+
+fn delta_e(a: &[[f32; 3]], b: &[[f32; 3]]) -> Vec<f32> {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| delta_e2000(lab(*a), lab(*b)))
+        .collect()
+}
+
+/// Compares `dithered` against the `reference` it was dithered from, both the
+/// same size. `sigma` is the viewing distance blur, in pixels.
+pub fn measure(reference: &RgbImage, dithered: &RgbImage, sigma: f32) -> Metrics {
+    let size = (reference.width() as usize, reference.height() as usize);
+    let (a, b) = (blur_rgb(reference, sigma), blur_rgb(dithered, sigma));
+    let de = delta_e(&a, &b);
+    Metrics {
+        psnr: psnr(&a, &b),
+        ssim: ssim(&a, &b, size),
+        delta_e_mean: de.iter().sum::<f32>() / de.len() as f32,
+        delta_e_max: de.iter().cloned().fold(0.0, f32::max),
+    }
+}
+
+/// The reference, the dithered image and a map of the blurred ΔE between
+/// them, left to right. The map is black where they match and white from a
+/// ΔE of 20 up.
+pub fn side_by_side(reference: &RgbImage, dithered: &RgbImage, sigma: f32) -> RgbImage {
+    let (w, h) = reference.dimensions();
+    let de = delta_e(&blur_rgb(reference, sigma), &blur_rgb(dithered, sigma));
+    let mut out = RgbImage::new(w * 3, h);
+    image::imageops::replace(&mut out, reference, 0, 0);
+    image::imageops::replace(&mut out, dithered, w, 0);
+    for (i, d) in de.iter().enumerate() {
+        let v = (d / 20.0 * 255.0).round().clamp(0.0, 255.0) as u8;
+        out.put_pixel(2 * w + i as u32 % w, i as u32 / w, Rgb([v, v, v]));
+    }
+    out
+}
@@ -1,3 +1,6 @@
+use crate::color::Metric;
+use crate::dither::{dither, DitherConfig};
+use crate::palette::PaletteConfig;
 use embedded_graphics::prelude::*;
 use epd_waveshare::prelude::*;
 use std::convert::TryInto;
@@ -13,7 +16,115 @@ impl OctSubpixel for u32 {}
 
 #[derive(Clone)]
 pub struct OctColorMap<T> {
-    pub colors: Vec<(OctColor, T)>,
+    colors: Vec<(OctColor, T)>,
+    metric: Metric,
+    rgb: Vec<[f32; 3]>,
+    lut: Option<Lut>,
+}
+
+fn rgb<T>(p: &T) -> [f32; 3]
+where
+    T: image::Pixel,
+    T::Subpixel: OctSubpixel,
+{
+    let max = ((1u64 << (8 * std::mem::size_of::<T::Subpixel>())) - 1) as f32;
+    let mut out = [0.0; 3];
+    out.iter_mut().zip(p.channels()).for_each(|(o, c)| {
+        let c: u32 = (*c).try_into().map_err(|_| ()).unwrap();
+        *o = c as f32 * 255.0 / max;
+    });
+    out
+}
+
+/// Cells along each axis of the lookup cube.
+const CELLS: usize = 32;
+
+/// RGB cube whose cells hold a bit mask of every color that can be nearest
+/// to some color inside them. Most cells have a single candidate, the rest
+/// are settled by measuring just the candidates.
+///
+/// The candidates are pruned with the triangle inequality, so the cube is
+/// only exact for metrics that satisfy it. Redmean and CIE76 were checked
+/// against a brute force search over random palettes; CIEDE2000 fails it.
+#[derive(Clone)]
+struct Lut {
+    cells: Vec<u16>,
+}
+
+fn nearest(colors: &[[f32; 3]], metric: Metric, color: [f32; 3]) -> usize {
+    colors
+        .iter()
+        .map(|c| metric.distance(*c, color))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .unwrap()
+        .0
+}
+
+impl Lut {
+    fn new(colors: &[[f32; 3]], metric: Metric) -> Lut {
+        let step = 256.0 / CELLS as f32;
+        let cells = (0..CELLS * CELLS * CELLS)
+            .map(|i| {
+                let lo =
+                    [i / (CELLS * CELLS), i / CELLS % CELLS, i % CELLS].map(|c| c as f32 * step);
+                let point = |t: [f32; 3]| [0, 1, 2].map(|c| (lo[c] + t[c] * step).min(255.0));
+                let center = point([0.5; 3]);
+                let radius = (0..8)
+                    .map(|c| {
+                        let corner = [c >> 2, c >> 1 & 1, c & 1].map(|t| t as f32);
+                        metric.distance(point(corner), center)
+                    })
+                    .fold(0.0, f32::max);
+                let distances: Vec<f32> =
+                    colors.iter().map(|c| metric.distance(*c, center)).collect();
+                let best = distances.iter().cloned().fold(f32::INFINITY, f32::min);
+                // By the triangle inequality, a color farther than this from
+                // the center can't be nearest anywhere in the cell.
+                distances
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, d)| **d <= best + 2.0 * radius)
+                    .fold(0, |mask, (i, _)| mask | 1 << i)
+            })
+            .collect();
+        Lut { cells }
+    }
+
+    fn candidates(&self, color: [f32; 3]) -> u16 {
+        let cell = |c: f32| ((c * CELLS as f32 / 256.0) as usize).min(CELLS - 1);
+        self.cells[(cell(color[0]) * CELLS + cell(color[1])) * CELLS + cell(color[2])]
+    }
+}
+
+impl<T> OctColorMap<T>
+where
+    T: image::Pixel,
+    T::Subpixel: OctSubpixel,
+{
+    pub fn new(colors: Vec<(OctColor, T)>, metric: Metric) -> Self {
+        let rgb: Vec<_> = colors.iter().map(|(_, c)| rgb(c)).collect();
+        let bounded = match metric {
+            Metric::Manhattan | Metric::Euclidean | Metric::Redmean | Metric::Cie76 => true,
+            Metric::Ciede2000 => false,
+        };
+        // Candidates are kept in a u16 mask.
+        let lut = if bounded && colors.len() <= 16 {
+            Some(Lut::new(&rgb, metric))
+        } else {
+            None
+        };
+        OctColorMap {
+            colors,
+            metric,
+            rgb,
+            lut,
+        }
+    }
+
+    pub fn color(&self, index: usize) -> T {
+        self.colors[index].1
+    }
 }
 
 impl<T> image::imageops::colorops::ColorMap for OctColorMap<T>
@@ -23,23 +134,18 @@ where
 {
     type Color = T;
     fn index_of(&self, color: &Self::Color) -> usize {
-        self.colors
-            .iter()
-            .map(|(_, c)| c)
-            .map(|c| {
-                c.channels()
-                    .iter()
-                    .zip(color.channels())
-                    .map(|(c1, c2)| {
-                        (std::cmp::max(*c1, *c2) - std::cmp::min(*c1, *c2))
-                            .try_into()
-                            .map_err(|_| ())
-                            .unwrap()
-                    })
-                    .sum::<u32>()
-            })
-            .enumerate()
-            .min_by_key(|(_i, s)| *s)
+        let color = rgb(color);
+        let mask = match &self.lut {
+            Some(lut) => lut.candidates(color),
+            None => return nearest(&self.rgb, self.metric, color),
+        };
+        if mask.is_power_of_two() {
+            return mask.trailing_zeros() as usize;
+        }
+        (0..self.rgb.len())
+            .filter(|i| mask & 1 << i != 0)
+            .map(|i| (i, self.metric.distance(self.rgb[i], color)))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
             .unwrap()
             .0
     }
@@ -48,97 +154,126 @@ where
     }
 }
 
-pub struct OctDither<P, C>
+/// Dithered image kept as one palette index per pixel.
+pub struct OctDither<P>
 where
     P: image::Pixel,
 {
-    buffer: image::ImageBuffer<P, C>,
+    indices: Vec<u8>,
+    size: Size,
     map: OctColorMap<P>,
+    /// Color of each of `map`'s inks in `output()`.
+    preview: Vec<P>,
     top_left: Point,
 }
 
-impl OctDither<image::Rgb<u8>, Vec<u8>> {
-    pub fn new(img: image::DynamicImage, map: OctColorMap<image::Rgb<u8>>, pt: Point) -> Self {
+impl OctDither<image::Rgb<u8>> {
+    pub fn new(
+        img: image::DynamicImage,
+        map: OctColorMap<image::Rgb<u8>>,
+        pt: Point,
+        cfg: &DitherConfig,
+    ) -> Self {
         let mut rgb = img.into_rgb8();
-        image::imageops::colorops::dither(&mut rgb, &map);
+        let indices = dither(&mut rgb, &map, cfg);
+        let preview = map
+            .colors
+            .iter()
+            .map(|(c, _)| {
+                let rgb = c.rgb();
+                image::Rgb([rgb.0, rgb.1, rgb.2])
+            })
+            .collect();
         OctDither {
-            buffer: rgb,
+            indices,
+            size: Size {
+                width: rgb.width(),
+                height: rgb.height(),
+            },
             map,
+            preview,
             top_left: pt,
         }
     }
 
-    pub fn output(&self) -> image::DynamicImage {
+    /// Forces every opaque pixel of `overlay` to its nearest ink after
+    /// dithering, so overlaid text and graphics keep hard edges.
+    pub fn stamp(&mut self, overlay: &image::RgbaImage) {
         use image::imageops::ColorMap;
-        let mut out = self.buffer.clone();
-        out.pixels_mut().for_each(|p| {
-            let rgb = self.map.colors[self.map.index_of(p)].0.rgb();
-            *p = image::Rgb([rgb.0, rgb.1, rgb.2]);
-        });
+        let map = &self.map;
+        self.indices
+            .iter_mut()
+            .zip(overlay.pixels())
+            .filter(|(_, o)| o[3] == 0xff)
+            .for_each(|(i, o)| *i = map.index_of(&image::Rgb([o[0], o[1], o[2]])) as u8);
+    }
+
+    pub fn output(&self) -> image::DynamicImage {
+        let mut out = image::RgbImage::new(self.size.width, self.size.height);
+        out.pixels_mut()
+            .zip(self.indices.iter())
+            .for_each(|(p, i)| *p = self.preview[*i as usize]);
 
         image::DynamicImage::ImageRgb8(out)
     }
 
-    pub fn iter(
-        &self,
-    ) -> embedded_graphics::iterator::contiguous::IntoPixels<DitherIter<image::Rgb<u8>>> {
-        DitherIter {
-            iter: self.buffer.pixels(),
-            map: &self.map,
-        }
-        .into_pixels(&embedded_graphics::primitives::rectangle::Rectangle {
-            top_left: self.top_left,
-            size: Size {
-                height: self.buffer.height(),
-                width: self.buffer.width(),
-            },
-        })
+    /// The inks in their measured colors, roughly what the panel shows.
+    pub fn render(&self) -> image::RgbImage {
+        let mut out = image::RgbImage::new(self.size.width, self.size.height);
+        out.pixels_mut()
+            .zip(self.indices.iter())
+            .for_each(|(p, i)| *p = self.map.colors[*i as usize].1);
+        out
     }
-}
 
-pub struct DitherIter<'a, P>
-where
-    P: image::Pixel,
-{
-    iter: image::buffer::Pixels<'a, P>,
-    map: &'a OctColorMap<P>,
-}
-impl<'a, P> Iterator for DitherIter<'a, P>
-where
-    P: image::Pixel,
-    P::Subpixel: OctSubpixel,
-{
-    type Item = OctColor;
-    fn next(&mut self) -> Option<Self::Item> {
-        use image::imageops::ColorMap;
-        self.iter
-            .next()
-            .map(|p| self.map.colors[self.map.index_of(p)].0)
+    /// Writes the inks into `buffer`, the panel's packed 4 bits per pixel
+    /// layout of `width`×`height` native pixels, as if drawn with `rotation`.
+    /// Pixels falling outside the panel are skipped.
+    pub fn pack(&self, buffer: &mut [u8], (width, height): (u32, u32), rotation: DisplayRotation) {
+        let (w, h) = match rotation {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => (width, height),
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => (height, width),
+        };
+        for (i, index) in self.indices.iter().enumerate() {
+            let x = self.top_left.x + (i as u32 % self.size.width) as i32;
+            let y = self.top_left.y + (i as u32 / self.size.width) as i32;
+            if x < 0 || y < 0 || x as u32 >= w || y as u32 >= h {
+                continue;
+            }
+            let (x, y) = (x as u32, y as u32);
+            let (nx, ny) = match rotation {
+                DisplayRotation::Rotate0 => (x, y),
+                DisplayRotation::Rotate90 => (width - 1 - y, x),
+                DisplayRotation::Rotate180 => (width - 1 - x, height - 1 - y),
+                DisplayRotation::Rotate270 => (y, height - 1 - x),
+            };
+            let byte = &mut buffer[(nx / 2 + width / 2 * ny) as usize];
+            let nibble = self.map.colors[*index as usize].0.get_nibble();
+            *byte = if nx % 2 == 0 {
+                *byte & 0x0f | nibble << 4
+            } else {
+                *byte & 0xf0 | nibble
+            };
+        }
     }
 }
 
-impl OctDither<image::Rgb<u8>, Vec<u8>> {
-    pub fn new_default(img: image::DynamicImage, pt: Point) -> Self {
-        static COLORS: [OctColor; 8] = [
-            OctColor::HiZ,
-            OctColor::White,
-            OctColor::Black,
-            OctColor::Red,
-            OctColor::Green,
-            OctColor::Orange,
-            OctColor::Blue,
-            OctColor::Yellow,
-        ];
-
-        let map = OctColorMap {
-            colors: COLORS
-                .iter()
-                .map(|c| {
-                    let rgb = c.rgb();
-                    (*c, image::Rgb::<u8>([rgb.0, rgb.1, rgb.2]))
-                })
-                .collect(),
-        };
-        Self::new(img, map, pt)
+impl OctDither<image::Rgb<u8>> {
+    /// Dithers with the measured inks of `palette`.
+    pub fn calibrated(
+        img: image::DynamicImage,
+        pt: Point,
+        palette: &PaletteConfig,
+        cfg: &DitherConfig,
+    ) -> Self {
+        let map = OctColorMap::new(palette.inks(), cfg.metric);
+        let mut dither = Self::new(img, map, pt, cfg);
+        dither.preview = dither
+            .map
+            .colors
+            .iter()
+            .map(|(ink, measured)| palette.preview(*ink, *measured))
+            .collect();
+        dither
     }
 }
@@ -0,0 +1,101 @@
+use epd_waveshare::color::OctColor;
+use image::Rgb;
+use serde_derive::Deserialize;
+
+/// Colors written to the preview PNG.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Preview {
+    /// The pure colors of `OctColor::rgb()`.
+    Nominal,
+    /// The measured colors, roughly what the panel will look like.
+    Calibrated,
+}
+
+/// Measured sRGB of every ink, used to pick inks while dithering, e.g.
+///
+/// ```toml
+/// [palette]
+/// black = [57, 48, 57]
+/// white = [220, 220, 212]
+/// hiz = [190, 190, 180]
+/// preview = "calibrated"
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PaletteConfig {
+    pub black: [u8; 3],
+    pub white: [u8; 3],
+    pub green: [u8; 3],
+    pub blue: [u8; 3],
+    pub red: [u8; 3],
+    pub yellow: [u8; 3],
+    pub orange: [u8; 3],
+    /// HiZ leaves the pixels floating rather than driving an ink, so it is
+    /// only dithered with when given a measured color.
+    pub hiz: Option<[u8; 3]>,
+    pub preview: Preview,
+}
+
+// Measurements of a 5.65" ACeP panel as published with Pimoroni's Inky
+// Impression library, with the white toned down to paper.
+impl Default for PaletteConfig {
+    fn default() -> Self {
+        PaletteConfig {
+            black: [57, 48, 57],
+            white: [232, 232, 224],
+            green: [58, 91, 70],
+            blue: [61, 59, 94],
+            red: [156, 72, 75],
+            yellow: [208, 190, 71],
+            orange: [177, 106, 73],
+            hiz: None,
+            preview: Preview::Nominal,
+        }
+    }
+}
+
+impl PaletteConfig {
+    /// Measured color of `ink`. Anything drawn to come out as a solid ink
+    /// should use this rather than `OctColor::rgb()`.
+    pub fn rgb(&self, ink: OctColor) -> Rgb<u8> {
+        Rgb(match ink {
+            OctColor::Black => self.black,
+            OctColor::White => self.white,
+            OctColor::Green => self.green,
+            OctColor::Blue => self.blue,
+            OctColor::Red => self.red,
+            OctColor::Yellow => self.yellow,
+            OctColor::Orange => self.orange,
+            OctColor::HiZ => self.hiz.unwrap_or(self.white),
+        })
+    }
+
+    /// The inks to dither with and their measured colors.
+    pub fn inks(&self) -> Vec<(OctColor, Rgb<u8>)> {
+        let mut inks = vec![
+            OctColor::White,
+            OctColor::Black,
+            OctColor::Red,
+            OctColor::Green,
+            OctColor::Orange,
+            OctColor::Blue,
+            OctColor::Yellow,
+        ];
+        if self.hiz.is_some() {
+            inks.push(OctColor::HiZ);
+        }
+        inks.into_iter().map(|ink| (ink, self.rgb(ink))).collect()
+    }
+
+    /// Color an ink is drawn with in the preview PNG.
+    pub fn preview(&self, ink: OctColor, measured: Rgb<u8>) -> Rgb<u8> {
+        match self.preview {
+            Preview::Nominal => {
+                let (r, g, b) = ink.rgb();
+                Rgb([r, g, b])
+            }
+            Preview::Calibrated => measured,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use image::{Rgba, RgbaImage};
+use imageproc::geometric_transformations::{Interpolation, Projection};
+use serde_derive::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Upscale {
+    /// Bicubic warp straight from the source, which blurs pixel art.
+    Bicubic,
+    /// Integer nearest neighbor upscale.
+    Nearest,
+    /// Repeated Scale2x, which rounds off diagonal staircases.
+    Scale2x,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct ScalingConfig {
+    pub mode: Upscale,
+    /// Samples per axis taken for each output pixel of a pixel art warp.
+    pub supersample: u32,
+}
+
+impl Default for ScalingConfig {
+    fn default() -> Self {
+        ScalingConfig {
+            mode: Upscale::Bicubic,
+            supersample: 3,
+        }
+    }
+}
+
+fn scale2x(img: &RgbaImage) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let get = |x: i64, y: i64| {
+        *img.get_pixel(
+            x.max(0).min(w as i64 - 1) as u32,
+            y.max(0).min(h as i64 - 1) as u32,
+        )
+    };
+    let mut out = RgbaImage::new(w * 2, h * 2);
+    for y in 0..h {
+        for x in 0..w {
+            let (xi, yi) = (x as i64, y as i64);
+            let p = get(xi, yi);
+            let a = get(xi, yi - 1);
+            let b = get(xi + 1, yi);
+            let c = get(xi - 1, yi);
+            let d = get(xi, yi + 1);
+            let pick = |n1: Rgba<u8>, n2: Rgba<u8>, o1: Rgba<u8>, o2: Rgba<u8>| {
+                if n1 == n2 && n1 != o1 && n2 != o2 {
+                    n1
+                } else {
+                    p
+                }
+            };
+            out.put_pixel(2 * x, 2 * y, pick(c, a, d, b));
+            out.put_pixel(2 * x + 1, 2 * y, pick(a, b, c, d));
+            out.put_pixel(2 * x, 2 * y + 1, pick(d, c, b, a));
+            out.put_pixel(2 * x + 1, 2 * y + 1, pick(b, d, a, c));
+        }
+    }
+    out
+}
+
+impl ScalingConfig {
+    pub fn pixel_art(&self) -> bool {
+        self.mode != Upscale::Bicubic
+    }
+
+    /// Upscales `img` by the smallest integer factor that reaches `target`
+    /// (capped at 8x), so the later warp only ever shrinks.
+    pub fn upscale(&self, img: &RgbaImage, target: (u32, u32)) -> RgbaImage {
+        let (w, h) = img.dimensions();
+        let factor = ((target.0 as f32 / w as f32).max(target.1 as f32 / h as f32))
+            .ceil()
+            .max(1.0)
+            .min(8.0) as u32;
+        match self.mode {
+            Upscale::Bicubic => img.clone(),
+            Upscale::Nearest => image::imageops::resize(
+                img,
+                w * factor,
+                h * factor,
+                image::imageops::FilterType::Nearest,
+            ),
+            Upscale::Scale2x => {
+                let mut out = img.clone();
+                let mut scale = 1;
+                while scale < factor {
+                    out = scale2x(&out);
+                    scale *= 2;
+                }
+                out
+            }
+        }
+    }
+
+    /// Warps `src` into `dst`, leaving `dst` transparent wherever `src`
+    /// doesn't land, the same as `imageproc`'s `warp_into` with a transparent
+    /// default.
+    pub fn warp_into(&self, src: &RgbaImage, proj: &Projection, dst: &mut RgbaImage) {
+        if self.pixel_art() {
+            supersample(src, proj, self.supersample.max(1), dst);
+        } else {
+            imageproc::geometric_transformations::warp_into(
+                src,
+                proj,
+                Interpolation::Bicubic,
+                Rgba([0, 0, 0, 0]),
+                dst,
+            );
+        }
+    }
+}
+
+/// Averages `ss` x `ss` nearest neighbor samples per output pixel, which keeps
+/// source pixels square while antialiasing their edges.
+fn supersample(src: &RgbaImage, proj: &Projection, ss: u32, dst: &mut RgbaImage) {
+    let inv = proj.invert();
+    let (sw, sh) = src.dimensions();
+    let (dw, dh) = dst.dimensions();
+    let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for corner in &[
+        (0.0, 0.0),
+        (sw as f32, 0.0),
+        (sw as f32, sh as f32),
+        (0.0, sh as f32),
+    ] {
+        let (x, y) = *proj * *corner;
+        x0 = x0.min(x);
+        y0 = y0.min(y);
+        x1 = x1.max(x);
+        y1 = y1.max(y);
+    }
+    let clamp = |v: f32, max: u32| v.max(0.0).min(max as f32) as u32;
+    let (x0, x1) = (clamp(x0.floor(), dw), clamp(x1.ceil(), dw));
+    let (y0, y1) = (clamp(y0.floor(), dh), clamp(y1.ceil(), dh));
+
+    dst.pixels_mut().for_each(|p| *p = Rgba([0, 0, 0, 0]));
+    let samples = (ss * ss) as f32;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let mut acc = [0.0f32; 4];
+            for sy in 0..ss {
+                for sx in 0..ss {
+                    let (u, v) = inv
+                        * (
+                            x as f32 + (sx as f32 + 0.5) / ss as f32,
+                            y as f32 + (sy as f32 + 0.5) / ss as f32,
+                        );
+                    if u >= 0.0 && v >= 0.0 && (u as u32) < sw && (v as u32) < sh {
+                        let s = src.get_pixel(u as u32, v as u32);
+                        let a = s[3] as f32;
+                        acc[0] += s[0] as f32 * a;
+                        acc[1] += s[1] as f32 * a;
+                        acc[2] += s[2] as f32 * a;
+                        acc[3] += a;
+                    }
+                }
+            }
+            if acc[3] > 0.0 {
+                dst.put_pixel(
+                    x,
+                    y,
+                    Rgba([
+                        (acc[0] / acc[3]).round() as u8,
+                        (acc[1] / acc[3]).round() as u8,
+                        (acc[2] / acc[3]).round() as u8,
+                        (acc[3] / samples).round() as u8,
+                    ]),
+                );
+            }
+        }
+    }
+}
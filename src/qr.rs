@@ -25,6 +25,13 @@ impl<C> QrCode<C> {
             bg_color,
         }
     }
+
+    /// Drawn size, including the quiet zone.
+    pub fn size(&self) -> Size {
+        let image = self.qr.render::<char>().build();
+        let modules = image.lines().count() as u32;
+        Size::new(modules, modules) * self.scale as u32
+    }
 }
 
 impl<C> Transform for QrCode<C>
@@ -1,3 +1,14 @@
+use crate::caption::CaptionConfig;
+use crate::dither::DitherConfig;
+use crate::layer::LayerConfig;
+use crate::lcd::{LcdConfig, Model};
+use crate::palette::PaletteConfig;
+use crate::pixelart::ScalingConfig;
+use crate::screen::{self, Quad};
+use crate::tone::ToneConfig;
+use crate::transform::TransformConfig;
+use crate::variants::Variants;
+use anyhow::anyhow;
 use serde_derive::Deserialize;
 use std::io::Read;
 use std::io::Result as IOResult;
@@ -136,10 +147,39 @@ struct Art {
 #[derive(Debug)]
 pub struct Rom {
     pub path: PathBuf,
+    pub name: String,
+    pub year: Option<u32>,
     lang: Vec<Country>,
     pub boxart: Option<PathBuf>,
 }
 
+impl Rom {
+    pub fn region(&self) -> Option<&'static str> {
+        self.lang.iter().find_map(|c| match c {
+            Country::USA => Some("USA"),
+            Country::Japan => Some("Japan"),
+            Country::Other => None,
+        })
+    }
+
+    /// Title from the cartridge header at 0x134, which is 16 bytes on DMG
+    /// carts and shorter on CGB carts where the tail holds other data.
+    pub fn header_title(&self) -> IOResult<String> {
+        let data = open_rom(&self.path)?;
+        let title = data.get(0x134..0x144).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::Other, "Rom too small for header")
+        })?;
+        Ok(title
+            .iter()
+            .take_while(|c| **c != 0)
+            .take_while(|c| c.is_ascii_graphic() || **c == b' ')
+            .map(|c| *c as char)
+            .collect::<String>()
+            .trim()
+            .to_string())
+    }
+}
+
 impl RomData {
     pub fn roms(&self) -> Vec<Rom> {
         use regex::Regex;
@@ -232,6 +272,7 @@ impl RomData {
 
         let attr_re = Regex::new(r"\(([^)]+?)\)").unwrap();
         let junk_re = Regex::new(r"\[([^]]+?)\]").unwrap();
+        let year_re = Regex::new(r"\b(19[89]\d|20[0-2]\d)\b").unwrap();
         WalkDir::new(&self.roms)
             .into_iter()
             .filter_map(Result::ok)
@@ -273,6 +314,8 @@ impl RomData {
                     .filter(|x| x.1 > 0.75)
                     .max_by(|x, y| x.1.partial_cmp(&y.1).unwrap());
                 Rom {
+                    name: search.to_string(),
+                    year: year_re.captures(name).and_then(|cap| cap[1].parse().ok()),
                     path: p,
                     boxart: best.map(|x| x.0.path.to_owned()),
                     lang: data,
@@ -284,13 +327,79 @@ impl RomData {
 
 #[derive(Deserialize)]
 pub struct GameboyImage {
-    pub screen: [(f32, f32); 4],
+    /// Detected from the image by `Config::detect_screens` if omitted.
+    pub screen: Option<Quad>,
     pub path: PathBuf,
     pub color: bool,
+    /// LCD look for the shell, `cgb` or `dmg` from `color` if omitted.
+    pub model: Option<Model>,
+}
+
+impl GameboyImage {
+    pub fn model(&self) -> Model {
+        self.model
+            .unwrap_or(if self.color { Model::Cgb } else { Model::Dmg })
+    }
+}
+
+/// Cartridge template whose label gets covered with the rom's boxart.
+#[derive(Deserialize)]
+pub struct CartridgeImage {
+    pub path: PathBuf,
+    /// Corners of the label: top left, top right, bottom right, bottom left.
+    pub label: Quad,
 }
 
 #[derive(Deserialize)]
 pub struct Config {
     pub romdata: Vec<RomData>,
     pub gameboy: Vec<GameboyImage>,
+    #[serde(default)]
+    pub background: Variants,
+    pub caption: Option<CaptionConfig>,
+    #[serde(default)]
+    pub transforms: TransformConfig,
+    #[serde(default)]
+    pub scaling: ScalingConfig,
+    pub lcd: Option<LcdConfig>,
+    #[serde(default)]
+    pub blend: Variants,
+    #[serde(default)]
+    pub shapes: Variants,
+    #[serde(default)]
+    pub frames: Variants,
+    pub layers: Option<LayerConfig>,
+    #[serde(default)]
+    pub cartridge: Vec<CartridgeImage>,
+    #[serde(default)]
+    pub dither: DitherConfig,
+    #[serde(default)]
+    pub palette: PaletteConfig,
+    pub tone: Option<ToneConfig>,
+}
+
+impl Config {
+    pub fn load<P>(path: P) -> anyhow::Result<Config>
+    where
+        P: AsRef<Path>,
+    {
+        let cfg: Config = toml::from_str(&std::fs::read_to_string(path)?)?;
+        if let Some(caption) = &cfg.caption {
+            caption.check()?;
+        }
+        Ok(cfg)
+    }
+
+    /// Finds the screen of every shell that doesn't give one. Only needed
+    /// before placing frames into the shells.
+    pub fn detect_screens(&mut self) -> anyhow::Result<()> {
+        for gb in self.gameboy.iter_mut().filter(|g| g.screen.is_none()) {
+            let img = image::open(&gb.path)?.to_rgba8();
+            let quad = screen::detect(&img, screen::Method::Auto)
+                .ok_or_else(|| anyhow!("No screen found in {}", gb.path.display()))?;
+            println!("Detected screen {:?} in {}", quad, gb.path.display());
+            gb.screen = Some(quad);
+        }
+        Ok(())
+    }
 }
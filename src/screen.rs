@@ -0,0 +1,178 @@
+use image::{GrayImage, Luma, RgbaImage};
+use imageproc::region_labelling::{connected_components, Connectivity};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub type Quad = [(f32, f32); 4];
+
+#[derive(Clone, Copy, Debug)]
+pub enum Method {
+    Auto,
+    Transparent,
+    Lcd,
+    Contour,
+}
+
+impl FromStr for Method {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Method::Auto),
+            "transparent" => Ok(Method::Transparent),
+            "lcd" => Ok(Method::Lcd),
+            "contour" => Ok(Method::Contour),
+            _ => Err(anyhow::anyhow!("Unknown detection method {}", s)),
+        }
+    }
+}
+
+struct Region {
+    area: u32,
+    border: bool,
+    // min(x+y), max(x-y), max(x+y), min(x-y) and the points that hit them
+    extremes: [(i64, (u32, u32)); 4],
+}
+
+impl Region {
+    fn new(x: u32, y: u32) -> Self {
+        let (s, d) = (x as i64 + y as i64, x as i64 - y as i64);
+        Region {
+            area: 0,
+            border: false,
+            extremes: [(s, (x, y)), (d, (x, y)), (s, (x, y)), (d, (x, y))],
+        }
+    }
+
+    fn add(&mut self, x: u32, y: u32, border: bool) {
+        let (s, d) = (x as i64 + y as i64, x as i64 - y as i64);
+        self.area += 1;
+        self.border |= border;
+        if s < self.extremes[0].0 {
+            self.extremes[0] = (s, (x, y));
+        }
+        if d > self.extremes[1].0 {
+            self.extremes[1] = (d, (x, y));
+        }
+        if s > self.extremes[2].0 {
+            self.extremes[2] = (s, (x, y));
+        }
+        if d < self.extremes[3].0 {
+            self.extremes[3] = (d, (x, y));
+        }
+    }
+
+    /// Corners in the same order `place` maps the frame onto: top left, top
+    /// right, bottom right, bottom left.
+    fn quad(&self) -> Quad {
+        let [tl, tr, br, bl] = self.extremes;
+        [
+            ((tl.1).0 as f32, (tl.1).1 as f32),
+            ((tr.1).0 as f32 + 1.0, (tr.1).1 as f32),
+            ((br.1).0 as f32 + 1.0, (br.1).1 as f32 + 1.0),
+            ((bl.1).0 as f32, (bl.1).1 as f32 + 1.0),
+        ]
+    }
+}
+
+fn quad_area(q: &Quad) -> f32 {
+    (0..4)
+        .map(|i| {
+            let (a, b) = (q[i], q[(i + 1) % 4]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f32>()
+        .abs()
+        / 2.0
+}
+
+/// Finds regions of nonzero pixels in `mask` that don't touch the image
+/// border and are big enough to plausibly be a screen.
+fn regions(mask: &GrayImage) -> Vec<Region> {
+    let (w, h) = mask.dimensions();
+    let labels = connected_components(mask, Connectivity::Four, Luma([0u8]));
+    let mut regions: HashMap<u32, Region> = HashMap::new();
+    for (x, y, l) in labels.enumerate_pixels() {
+        if l[0] == 0 {
+            continue;
+        }
+        let border = x == 0 || y == 0 || x == w - 1 || y == h - 1;
+        regions
+            .entry(l[0])
+            .or_insert_with(|| Region::new(x, y))
+            .add(x, y, border);
+    }
+    let min_area = w * h / 100;
+    regions
+        .into_iter()
+        .map(|(_, r)| r)
+        .filter(|r| !r.border && r.area >= min_area)
+        .collect()
+}
+
+fn largest(mask: &GrayImage) -> Option<Quad> {
+    regions(mask)
+        .into_iter()
+        .max_by_key(|r| r.area)
+        .map(|r| r.quad())
+}
+
+fn select<F>(img: &RgbaImage, f: F) -> GrayImage
+where
+    F: Fn(&image::Rgba<u8>) -> bool,
+{
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        Luma([if f(img.get_pixel(x, y)) { 0xff } else { 0 }])
+    })
+}
+
+fn transparent(img: &RgbaImage) -> Option<Quad> {
+    largest(&select(img, |p| p[3] < 0x80))
+}
+
+/// Pea soup green of a DMG screen, or the dark glass of an unlit one.
+fn lcd_like(p: &image::Rgba<u8>) -> bool {
+    if p[3] < 0x80 {
+        return false;
+    }
+    let [r, g, b] = [p[0] as f32, p[1] as f32, p[2] as f32];
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let sat = if max > 0.0 { (max - min) / max } else { 0.0 };
+    let value = max / 255.0;
+    let greenish = g >= r && g > b && sat > 0.2 && value > 0.2;
+    let dark = sat < 0.15 && value < 0.35;
+    greenish || dark
+}
+
+fn lcd(img: &RgbaImage) -> Option<Quad> {
+    largest(&select(img, lcd_like))
+}
+
+fn contour(img: &RgbaImage) -> Option<Quad> {
+    use imageproc::distance_transform::Norm;
+    let gray = image::DynamicImage::ImageRgba8(img.clone()).to_luma8();
+    let edges = imageproc::edges::canny(&gray, 20.0, 60.0);
+    let mut mask = imageproc::morphology::dilate(&edges, Norm::LInf, 1);
+    image::imageops::invert(&mut mask);
+    regions(&mask)
+        .into_iter()
+        .map(|r| {
+            let quad = r.quad();
+            let fill = r.area as f32 / quad_area(&quad).max(1.0);
+            (r, quad, fill)
+        })
+        .filter(|(_, _, fill)| *fill > 0.85)
+        .max_by_key(|(r, _, _)| r.area)
+        .map(|(_, quad, _)| quad)
+}
+
+pub fn detect(img: &RgbaImage, method: Method) -> Option<Quad> {
+    match method {
+        Method::Auto => transparent(img)
+            .or_else(|| lcd(img))
+            .or_else(|| contour(img)),
+        Method::Transparent => transparent(img),
+        Method::Lcd => lcd(img),
+        Method::Contour => contour(img),
+    }
+}
@@ -0,0 +1,199 @@
+use crate::variants::{Params, Variants};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use imageproc::distance_transform::Norm;
+use rand::Rng;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shape {
+    Rect,
+    Circle,
+    RoundedRect(f32),
+    Star(u32, f32),
+    Hexagon,
+    Cartridge,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Frame {
+    None,
+    /// White border as a fraction of the shorter side, with a deeper bottom.
+    Polaroid(f32),
+    /// White stroke of the given width around the visible pixels.
+    Sticker(u32),
+}
+
+/// Even-odd test of `(x, y)` against a closed polygon.
+fn in_polygon(poly: &[(f32, f32)], (x, y): (f32, f32)) -> bool {
+    let mut inside = false;
+    let mut j = poly.len() - 1;
+    for i in 0..poly.len() {
+        let ((xi, yi), (xj, yj)) = (poly[i], poly[j]);
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Vertices alternating between `outer` and `inner` radius, starting at the
+/// top, `points` of each.
+fn star(center: (f32, f32), outer: f32, inner: f32, points: u32) -> Vec<(f32, f32)> {
+    (0..2 * points)
+        .map(|i| {
+            let r = if i % 2 == 0 { outer } else { inner };
+            let angle =
+                std::f32::consts::PI * i as f32 / points as f32 - std::f32::consts::FRAC_PI_2;
+            (center.0 + r * angle.cos(), center.1 + r * angle.sin())
+        })
+        .collect()
+}
+
+fn in_rounded_rect((x, y): (f32, f32), (w, h): (f32, f32), r: f32) -> bool {
+    let cx = x.clamp(r, w - r);
+    let cy = y.clamp(r, h - r);
+    (x - cx).powi(2) + (y - cy).powi(2) <= r * r
+}
+
+type Generator<R, T> = Box<dyn Fn(&mut R, Params) -> T>;
+
+impl Shape {
+    pub fn random<R>(mut rng: R, cfg: &Variants) -> Shape
+    where
+        R: Rng,
+    {
+        let v: Vec<(_, _, Generator<R, Shape>)> = vec![
+            ("rect", 6, Box::new(|_, _| Shape::Rect)),
+            ("circle", 1, Box::new(|_, _| Shape::Circle)),
+            (
+                "rounded_rect",
+                1,
+                Box::new(|mut rng, p| Shape::RoundedRect(p.f32(&mut rng, "radius", 0.05..0.25))),
+            ),
+            (
+                "star",
+                1,
+                Box::new(|mut rng, p| {
+                    Shape::Star(
+                        p.u32(&mut rng, "points", 5..9),
+                        p.f32(&mut rng, "inner", 0.4..0.7),
+                    )
+                }),
+            ),
+            ("hexagon", 1, Box::new(|_, _| Shape::Hexagon)),
+            ("cartridge", 1, Box::new(|_, _| Shape::Cartridge)),
+        ];
+        cfg.choose(&mut rng, &v)
+            .map(|(name, _, f)| f(&mut rng, cfg.params(name)))
+            .unwrap_or(Shape::Rect)
+    }
+
+    fn contains(&self, pt: (f32, f32), (w, h): (f32, f32)) -> bool {
+        let center = (w / 2.0, h / 2.0);
+        let radius = w.min(h) / 2.0;
+        match *self {
+            Shape::Rect => true,
+            Shape::Circle => (pt.0 - center.0).hypot(pt.1 - center.1) <= radius,
+            Shape::RoundedRect(r) => in_rounded_rect(pt, (w, h), r * w.min(h)),
+            Shape::Star(points, inner) => {
+                in_polygon(&star(center, radius, radius * inner, points.max(3)), pt)
+            }
+            Shape::Hexagon => in_polygon(&star(center, radius, radius, 3), pt),
+            Shape::Cartridge => {
+                // Game Boy cartridge: the top right corner is cut off and the
+                // bottom corners are slightly rounded.
+                let cut = 0.12 * w.min(h);
+                let notch = pt.0 - (w - cut) > pt.1;
+                let r = 0.04 * w.min(h);
+                let bottom = pt.1 > h - r;
+                !notch && (!bottom || in_rounded_rect(pt, (w, h), r))
+            }
+        }
+    }
+
+    /// Multiplies `img`'s alpha by the shape's coverage, antialiased with
+    /// 4x4 samples per pixel.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        if *self == Shape::Rect {
+            return;
+        }
+        const SS: u32 = 4;
+        let dim = (img.width() as f32, img.height() as f32);
+        for (x, y, p) in img.enumerate_pixels_mut() {
+            let hits = (0..SS * SS)
+                .filter(|i| {
+                    let sx = x as f32 + ((i % SS) as f32 + 0.5) / SS as f32;
+                    let sy = y as f32 + ((i / SS) as f32 + 0.5) / SS as f32;
+                    self.contains((sx, sy), dim)
+                })
+                .count() as u32;
+            p[3] = (p[3] as u32 * hits / (SS * SS)) as u8;
+        }
+    }
+}
+
+/// Pixels within `width` of any visible pixel of `img`.
+pub fn outline(img: &RgbaImage, width: u8) -> GrayImage {
+    let alpha = GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        Luma([if img.get_pixel(x, y)[3] > 0x80 {
+            0xff
+        } else {
+            0
+        }])
+    });
+    imageproc::morphology::dilate(&alpha, Norm::LInf, width)
+}
+
+impl Frame {
+    pub fn random<R>(mut rng: R, cfg: &Variants) -> Frame
+    where
+        R: Rng,
+    {
+        let v: Vec<(_, _, Generator<R, Frame>)> = vec![
+            ("none", 6, Box::new(|_, _| Frame::None)),
+            (
+                "polaroid",
+                1,
+                Box::new(|mut rng, p| Frame::Polaroid(p.f32(&mut rng, "border", 0.04..0.08))),
+            ),
+            (
+                "sticker",
+                1,
+                Box::new(|mut rng, p| Frame::Sticker(p.u32(&mut rng, "width", 4..12))),
+            ),
+        ];
+        cfg.choose(&mut rng, &v)
+            .map(|(name, _, f)| f(&mut rng, cfg.params(name)))
+            .unwrap_or(Frame::None)
+    }
+
+    /// Returns `img` with the frame around it, grown to fit.
+    pub fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let white = Rgba([0xff, 0xff, 0xff, 0xff]);
+        let (w, h) = img.dimensions();
+        match *self {
+            Frame::None => img.clone(),
+            Frame::Polaroid(border) => {
+                let side = (border * w.min(h) as f32).round().max(1.0) as u32;
+                let mut out = RgbaImage::from_pixel(w + 2 * side, h + 5 * side, white);
+                image::imageops::overlay(&mut out, img, side, side);
+                out
+            }
+            Frame::Sticker(width) => {
+                let width = width.min(u8::MAX as u32);
+                let mut padded = RgbaImage::new(w + 2 * width, h + 2 * width);
+                image::imageops::replace(&mut padded, img, width, width);
+                let stroke = outline(&padded, width as u8);
+                let mut out = RgbaImage::from_fn(padded.width(), padded.height(), |x, y| {
+                    if stroke.get_pixel(x, y)[0] != 0 {
+                        white
+                    } else {
+                        Rgba([0, 0, 0, 0])
+                    }
+                });
+                image::imageops::overlay(&mut out, &padded, 0, 0);
+                out
+            }
+        }
+    }
+}
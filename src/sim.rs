@@ -1,4 +1,4 @@
-use embedded_graphics::prelude::*;
+use embedded_graphics::{pixelcolor::Rgb888, prelude::*};
 use embedded_hal::{
     blocking::delay::*,
     blocking::spi::Write,
@@ -212,3 +212,81 @@ pub fn create() -> (
         .unwrap();
     (spi, delay, disp)
 }
+
+fn show(display: &mut SimulatorDisplay<Rgb888>, img: &image::RgbaImage, scale: f32) {
+    let img = image::imageops::resize(
+        img,
+        (img.width() as f32 * scale) as u32,
+        (img.height() as f32 * scale) as u32,
+        image::imageops::FilterType::Triangle,
+    );
+    let blend = |c: u8, a: u8| ((c as u32 * a as u32 + 0x80 * (0xff - a as u32)) / 0xff) as u8;
+    display
+        .draw_iter(img.enumerate_pixels().map(|(x, y, p)| {
+            Pixel(
+                Point::new(x as i32, y as i32),
+                Rgb888::new(blend(p[0], p[3]), blend(p[1], p[3]), blend(p[2], p[3])),
+            )
+        }))
+        .unwrap();
+}
+
+/// Shows `shell` and collects four clicks as the screen corners, in the
+/// order top left, top right, bottom right, bottom left. The result of
+/// `preview` for those corners is then shown until the window is clicked
+/// again to accept it. Closing the window aborts.
+pub fn calibrate<F>(shell: &image::RgbaImage, preview: F) -> Option<[(f32, f32); 4]>
+where
+    F: Fn([(f32, f32); 4]) -> Option<image::RgbaImage>,
+{
+    use embedded_graphics::primitives::{Circle, PrimitiveStyle};
+
+    let scale = (1000.0 / shell.width() as f32)
+        .min(800.0 / shell.height() as f32)
+        .min(1.0);
+    let mut display = SimulatorDisplay::<Rgb888>::new(Size::new(
+        (shell.width() as f32 * scale) as u32,
+        (shell.height() as f32 * scale) as u32,
+    ));
+    let output_settings = OutputSettingsBuilder::new().build();
+    let mut window = Window::new("Calibrate", &output_settings);
+    show(&mut display, shell, scale);
+    window.update(&display);
+
+    let mut corners = vec![];
+    loop {
+        for event in window.events() {
+            match event {
+                SimulatorEvent::MouseButtonUp { point, .. } if corners.len() < 4 => {
+                    println!("Corner {} at ({}, {})", corners.len(), point.x, point.y);
+                    corners.push((point.x as f32 / scale, point.y as f32 / scale));
+                    Circle::with_center(point, 7)
+                        .into_styled(PrimitiveStyle::with_stroke(Rgb888::RED, 2))
+                        .draw(&mut display)
+                        .unwrap();
+                    if corners.len() == 4 {
+                        let quad = [corners[0], corners[1], corners[2], corners[3]];
+                        match preview(quad) {
+                            Some(img) => {
+                                show(&mut display, &img, scale);
+                                println!("Click to accept, close the window to abort");
+                            }
+                            None => {
+                                println!("Corners don't form a screen, click them again");
+                                corners.clear();
+                                show(&mut display, shell, scale);
+                            }
+                        }
+                    }
+                }
+                SimulatorEvent::MouseButtonUp { .. } => {
+                    return Some([corners[0], corners[1], corners[2], corners[3]]);
+                }
+                SimulatorEvent::Quit => return None,
+                _ => {}
+            }
+        }
+        window.update(&display);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
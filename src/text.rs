@@ -0,0 +1,205 @@
+use anyhow::{anyhow, Result};
+use embedded_graphics::{
+    mono_font::{iso_8859_1, MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use imageproc::distance_transform::Norm;
+use rusttype::{point, Font, GlyphId, PositionedGlyph, Scale};
+use std::path::Path;
+
+/// Either one of the embedded-graphics mono fonts or a list of TrueType/OpenType
+/// fonts, where later fonts supply any glyphs missing from earlier ones (e.g. a
+/// CJK font after a Latin one).
+pub enum Face {
+    Mono(&'static MonoFont<'static>),
+    TrueType(Vec<Font<'static>>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    pub size: f32,
+    pub weight: u8,
+    pub color: Rgba<u8>,
+    pub outline: Option<(u8, Rgba<u8>)>,
+    pub shadow: Option<((i32, i32), Rgba<u8>)>,
+}
+
+struct MaskCanvas<'a>(&'a mut GrayImage);
+
+impl<'a> OriginDimensions for MaskCanvas<'a> {
+    fn size(&self) -> Size {
+        Size::new(self.0.width(), self.0.height())
+    }
+}
+
+impl<'a> DrawTarget for MaskCanvas<'a> {
+    type Color = BinaryColor;
+    type Error = std::convert::Infallible;
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (w, h) = self.0.dimensions();
+        pixels
+            .into_iter()
+            .filter(|Pixel(pt, c)| {
+                c.is_on() && pt.x >= 0 && pt.y >= 0 && (pt.x as u32) < w && (pt.y as u32) < h
+            })
+            .for_each(|Pixel(pt, _)| self.0.put_pixel(pt.x as u32, pt.y as u32, Luma([0xff])));
+        Ok(())
+    }
+}
+
+impl Face {
+    pub fn mono(name: &str) -> Result<Face> {
+        Ok(Face::Mono(match name {
+            "6x10" => &iso_8859_1::FONT_6X10,
+            "8x13" => &iso_8859_1::FONT_8X13,
+            "9x15" => &iso_8859_1::FONT_9X15,
+            "10x20" => &iso_8859_1::FONT_10X20,
+            _ => return Err(anyhow!("Unknown font {}", name)),
+        }))
+    }
+
+    pub fn load<P>(paths: &[P]) -> Result<Face>
+    where
+        P: AsRef<Path>,
+    {
+        let fonts = paths
+            .iter()
+            .map(|p| {
+                let p = p.as_ref();
+                Font::try_from_vec(std::fs::read(p)?)
+                    .ok_or_else(|| anyhow!("Invalid font {}", p.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if fonts.is_empty() {
+            return Err(anyhow!("No fonts given"));
+        }
+        Ok(Face::TrueType(fonts))
+    }
+
+    pub fn line_height(&self, size: f32) -> u32 {
+        match self {
+            Face::Mono(font) => font.character_size.height,
+            Face::TrueType(fonts) => {
+                let v = fonts[0].v_metrics(Scale::uniform(size));
+                (v.ascent - v.descent + v.line_gap).ceil() as u32
+            }
+        }
+    }
+
+    fn layout<'a>(
+        fonts: &'a [Font<'static>],
+        text: &str,
+        size: f32,
+        origin: (f32, f32),
+    ) -> (Vec<PositionedGlyph<'a>>, f32) {
+        let scale = Scale::uniform(size);
+        let ascent = fonts[0].v_metrics(scale).ascent;
+        let mut x = origin.0;
+        let mut last: Option<(usize, GlyphId)> = None;
+        let glyphs = text
+            .chars()
+            .map(|c| {
+                let idx = fonts
+                    .iter()
+                    .position(|f| f.glyph(c).id().0 != 0)
+                    .unwrap_or(0);
+                let glyph = fonts[idx].glyph(c).scaled(scale);
+                if let Some((last_idx, last_id)) = last {
+                    if last_idx == idx {
+                        x += fonts[idx].pair_kerning(scale, last_id, glyph.id());
+                    }
+                }
+                last = Some((idx, glyph.id()));
+                let advance = glyph.h_metrics().advance_width;
+                let glyph = glyph.positioned(point(x, origin.1 + ascent));
+                x += advance;
+                glyph
+            })
+            .collect();
+        (glyphs, x - origin.0)
+    }
+
+    pub fn width(&self, text: &str, size: f32) -> u32 {
+        match self {
+            Face::Mono(font) => {
+                text.chars().count() as u32 * (font.character_size.width + font.character_spacing)
+            }
+            Face::TrueType(fonts) => Self::layout(fonts, text, size, (0.0, 0.0)).1.ceil() as u32,
+        }
+    }
+
+    /// Coverage of `text` thresholded to fully on or off, so every text pixel
+    /// maps to exactly one ink instead of being dithered at the edges.
+    pub fn mask(&self, text: &str, size: f32, margin: u32) -> GrayImage {
+        let mut mask = GrayImage::new(
+            self.width(text, size) + 2 * margin,
+            self.line_height(size) + 2 * margin,
+        );
+        match self {
+            Face::Mono(font) => {
+                Text::with_baseline(
+                    text,
+                    Point::new(margin as i32, margin as i32),
+                    MonoTextStyle::new(font, BinaryColor::On),
+                    Baseline::Top,
+                )
+                .draw(&mut MaskCanvas(&mut mask))
+                .expect("Wrote Text");
+            }
+            Face::TrueType(fonts) => {
+                let (w, h) = mask.dimensions();
+                let (glyphs, _) = Self::layout(fonts, text, size, (margin as f32, margin as f32));
+                for g in glyphs {
+                    if let Some(bb) = g.pixel_bounding_box() {
+                        g.draw(|gx, gy, v| {
+                            let (x, y) = (gx as i32 + bb.min.x, gy as i32 + bb.min.y);
+                            if v >= 0.5 && x >= 0 && y >= 0 && (x as u32) < w && (y as u32) < h {
+                                mask.put_pixel(x as u32, y as u32, Luma([0xff]));
+                            }
+                        });
+                    }
+                }
+            }
+        }
+        mask
+    }
+}
+
+fn paint(target: &mut RgbaImage, mask: &GrayImage, (x, y): (i32, i32), color: Rgba<u8>) {
+    let (w, h) = target.dimensions();
+    mask.enumerate_pixels()
+        .filter(|(_, _, p)| p[0] != 0)
+        .map(|(mx, my, _)| (x + mx as i32, y + my as i32))
+        .filter(|(tx, ty)| *tx >= 0 && *ty >= 0 && (*tx as u32) < w && (*ty as u32) < h)
+        .for_each(|(tx, ty)| target.put_pixel(tx as u32, ty as u32, color));
+}
+
+/// Draws a single line of text with its line box's top left corner at `pos`.
+pub fn draw(target: &mut RgbaImage, face: &Face, text: &str, pos: (i32, i32), style: &TextStyle) {
+    use imageproc::morphology::dilate;
+    let outline = style.outline.map(|(w, _)| w).unwrap_or(0);
+    let margin = style.weight as u32 + outline as u32;
+    let mut mask = face.mask(text, style.size, margin);
+    if style.weight > 0 {
+        mask = dilate(&mask, Norm::LInf, style.weight);
+    }
+    let outer = if outline > 0 {
+        dilate(&mask, Norm::LInf, outline)
+    } else {
+        mask.clone()
+    };
+    let origin = (pos.0 - margin as i32, pos.1 - margin as i32);
+    if let Some(((dx, dy), color)) = style.shadow {
+        paint(target, &outer, (origin.0 + dx, origin.1 + dy), color);
+    }
+    if let Some((_, color)) = style.outline {
+        paint(target, &outer, origin, color);
+    }
+    paint(target, &mask, origin, style.color);
+}
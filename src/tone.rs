@@ -0,0 +1,185 @@
+use image::RgbaImage;
+use serde_derive::Deserialize;
+
+/// Contrast limited adaptive histogram equalization of the luma.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Clahe {
+    /// Tiles along each axis.
+    pub tiles: u32,
+    /// Histogram bins are clipped at this multiple of the mean bin count.
+    pub clip: f32,
+}
+
+impl Default for Clahe {
+    fn default() -> Self {
+        Clahe {
+            tiles: 8,
+            clip: 2.0,
+        }
+    }
+}
+
+/// Adjustments made to the composition right before it is dithered, so
+/// gradients land on the panel's few inks instead of turning into noise.
+/// Steps run in field order; the defaults suit the 7 color panel, e.g.
+///
+/// ```toml
+/// [tone]
+/// gamma = 1.1
+/// saturation = 1.4
+/// [tone.clahe]
+/// tiles = 6
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ToneConfig {
+    /// Fraction of pixels clipped at each end when stretching the levels,
+    /// 0 disables the stretch.
+    pub levels: f32,
+    pub clahe: Option<Clahe>,
+    /// Values above 1 brighten the midtones.
+    pub gamma: f32,
+    pub saturation: f32,
+    /// Unsharp mask strength, 0 disables sharpening.
+    pub sharpen: f32,
+    pub sharpen_sigma: f32,
+}
+
+impl Default for ToneConfig {
+    fn default() -> Self {
+        ToneConfig {
+            levels: 0.005,
+            clahe: None,
+            gamma: 1.0,
+            saturation: 1.3,
+            sharpen: 0.5,
+            sharpen_sigma: 1.0,
+        }
+    }
+}
+
+fn luma(p: &[f32; 3]) -> f32 {
+    0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2]
+}
+
+/// Stretches the channels so the luma spans the full range, ignoring the
+/// darkest and brightest `clip` of the pixels.
+fn levels(buf: &mut [[f32; 3]], clip: f32) {
+    let mut hist = [0usize; 256];
+    buf.iter()
+        .for_each(|p| hist[luma(p).round().clamp(0.0, 255.0) as usize] += 1);
+    let skip = (buf.len() as f32 * clip) as usize;
+    let find = |bins: Vec<usize>| {
+        let mut seen = 0;
+        bins.into_iter()
+            .find(|b| {
+                seen += hist[*b];
+                seen > skip
+            })
+            .unwrap_or(0) as f32
+    };
+    let lo = find((0..256).collect());
+    let hi = find((0..256).rev().collect());
+    if hi - lo < 1.0 {
+        return;
+    }
+    buf.iter_mut().flatten().for_each(|c| {
+        *c = (*c - lo) * 255.0 / (hi - lo);
+    });
+}
+
+fn clahe(buf: &mut [[f32; 3]], (w, h): (u32, u32), cfg: &Clahe) {
+    let tiles = cfg.tiles.max(1) as usize;
+    let (w, h) = (w as usize, h as usize);
+    let (tw, th) = (w.div_ceil(tiles), h.div_ceil(tiles));
+    // Equalization curve of every tile.
+    let curves: Vec<[f32; 256]> = (0..tiles * tiles)
+        .map(|t| {
+            let (tx, ty) = (t % tiles, t / tiles);
+            let mut hist = [0f32; 256];
+            let mut count = 0.0;
+            for y in ty * th..((ty + 1) * th).min(h) {
+                for x in tx * tw..((tx + 1) * tw).min(w) {
+                    hist[luma(&buf[y * w + x]).round().clamp(0.0, 255.0) as usize] += 1.0;
+                    count += 1.0;
+                }
+            }
+            let limit = (cfg.clip * count / 256.0).max(1.0);
+            let excess: f32 = hist.iter().map(|n| (n - limit).max(0.0)).sum();
+            hist.iter_mut()
+                .for_each(|n| *n = n.min(limit) + excess / 256.0);
+            let mut curve = [0f32; 256];
+            let mut sum = 0.0;
+            for (c, n) in curve.iter_mut().zip(hist.iter()) {
+                sum += n;
+                *c = sum * 255.0 / count.max(1.0);
+            }
+            curve
+        })
+        .collect();
+    // Bilinear interpolation between the curves of the nearest tile centers.
+    let axis = |v: usize, size: usize| {
+        let t = ((v as f32 + 0.5) / size as f32 - 0.5).clamp(0.0, (tiles - 1) as f32);
+        let i = (t as usize).min(tiles.saturating_sub(2));
+        (i, (i + 1).min(tiles - 1), t - i as f32)
+    };
+    for y in 0..h {
+        let (y0, y1, fy) = axis(y, th);
+        for x in 0..w {
+            let (x0, x1, fx) = axis(x, tw);
+            let p = &mut buf[y * w + x];
+            let l = luma(p);
+            let bin = l.round().clamp(0.0, 255.0) as usize;
+            let at = |tx: usize, ty: usize| curves[ty * tiles + tx][bin];
+            let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+            let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+            let delta = top * (1.0 - fy) + bottom * fy - l;
+            p.iter_mut().for_each(|c| *c += delta);
+        }
+    }
+}
+
+fn unsharp(img: &mut RgbaImage, amount: f32, sigma: f32) {
+    let blurred = imageproc::filter::gaussian_blur_f32(img, sigma);
+    img.pixels_mut().zip(blurred.pixels()).for_each(|(p, b)| {
+        for c in 0..3 {
+            let v = p[c] as f32 + amount * (p[c] as f32 - b[c] as f32);
+            p[c] = v.round().clamp(0.0, 255.0) as u8;
+        }
+    });
+}
+
+impl ToneConfig {
+    /// Tone maps the color channels of `img` in place, alpha is untouched.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        let mut buf: Vec<[f32; 3]> = img
+            .pixels()
+            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+            .collect();
+        if self.levels > 0.0 {
+            levels(&mut buf, self.levels);
+        }
+        if let Some(cfg) = &self.clahe {
+            clahe(&mut buf, img.dimensions(), cfg);
+        }
+        let gamma = 1.0 / self.gamma.max(0.01);
+        buf.iter_mut().for_each(|p| {
+            for c in p.iter_mut() {
+                *c = 255.0 * (c.clamp(0.0, 255.0) / 255.0).powf(gamma);
+            }
+            let l = luma(p);
+            for c in p.iter_mut() {
+                *c = l + (*c - l) * self.saturation;
+            }
+        });
+        img.pixels_mut().zip(buf.iter()).for_each(|(p, c)| {
+            for i in 0..3 {
+                p[i] = c[i].round().clamp(0.0, 255.0) as u8;
+            }
+        });
+        if self.sharpen > 0.0 && self.sharpen_sigma > 0.0 {
+            unsharp(img, self.sharpen, self.sharpen_sigma);
+        }
+    }
+}
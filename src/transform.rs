@@ -1,16 +1,187 @@
+use crate::chain::{Chain, Step};
+use crate::glitch;
+use crate::variants::{Params, Variants};
 use image::GenericImageView;
 use image::{DynamicImage, Rgba};
 use imageproc::geometric_transformations::Projection;
-use rand::prelude::*;
 use rand::Rng;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Transform {
     Edges(f32, f32, Rgba<u8>, Rgba<u8>),
     OverlayEdges(f32, f32, Rgba<u8>),
     Noise(f64, f64, u64),
     Threshold(u32, Rgba<u8>, Rgba<u8>),
     Blur(f32),
+    HueRotate(i32),
+    Saturation(f32),
+    Contrast(f32),
+    Brightness(i32),
+    Posterize(u8),
+    GradientMap(Rgba<u8>, Rgba<u8>),
+    Palette(usize),
+    Swirl(f32, f32),
+    Wave(f32, f32, f32),
+    Ripple(f32, f32),
+    Fisheye(f32),
+    Polar,
+    Kaleidoscope(u32, f32),
+    Tile(u32, u32, bool),
+    ChannelShift(u32, u64),
+    ScanlineJitter(u32, f32, u64),
+    PixelSort(f32, f32, bool),
+    BlockShift(u32, u32, u64),
+    DataBend(f32, u64),
+}
+
+/// What a transform does with the alpha of its input. `Respect` keeps the
+/// input's silhouette and alpha, `Bleed` keeps whatever alpha the transform
+/// produces, so it can spill past or reshape the silhouette.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Alpha {
+    Respect,
+    Bleed,
+}
+
+impl Transform {
+    /// Name used in config and in chains.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::Edges(..) => "edges",
+            Transform::OverlayEdges(..) => "overlay_edges",
+            Transform::Noise(..) => "noise",
+            Transform::Threshold(..) => "threshold",
+            Transform::Blur(..) => "blur",
+            Transform::HueRotate(..) => "hue_rotate",
+            Transform::Saturation(..) => "saturation",
+            Transform::Contrast(..) => "contrast",
+            Transform::Brightness(..) => "brightness",
+            Transform::Posterize(..) => "posterize",
+            Transform::GradientMap(..) => "gradient_map",
+            Transform::Palette(..) => "palette",
+            Transform::Swirl(..) => "swirl",
+            Transform::Wave(..) => "wave",
+            Transform::Ripple(..) => "ripple",
+            Transform::Fisheye(..) => "fisheye",
+            Transform::Polar => "polar",
+            Transform::Kaleidoscope(..) => "kaleidoscope",
+            Transform::Tile(..) => "tile",
+            Transform::ChannelShift(..) => "channel_shift",
+            Transform::ScanlineJitter(..) => "scanline_jitter",
+            Transform::PixelSort(..) => "pixel_sort",
+            Transform::BlockShift(..) => "block_shift",
+            Transform::DataBend(..) => "data_bend",
+        }
+    }
+
+    /// Blurs and geometric transforms reshape the silhouette unless told
+    /// otherwise, everything else keeps it.
+    pub fn default_alpha(&self) -> Alpha {
+        match self {
+            Transform::Blur(..)
+            | Transform::Swirl(..)
+            | Transform::Wave(..)
+            | Transform::Ripple(..)
+            | Transform::Fisheye(..)
+            | Transform::Polar
+            | Transform::Kaleidoscope(..)
+            | Transform::Tile(..)
+            | Transform::ChannelShift(..)
+            | Transform::PixelSort(..)
+            | Transform::DataBend(..) => Alpha::Bleed,
+            _ => Alpha::Respect,
+        }
+    }
+}
+
+/// Four shade palettes, lightest first, that `Transform::Palette` swaps to.
+pub static PALETTES: [[[u8; 3]; 4]; 6] = [
+    // DMG
+    [
+        [0x9b, 0xbc, 0x0f],
+        [0x8b, 0xac, 0x0f],
+        [0x30, 0x62, 0x30],
+        [0x0f, 0x38, 0x0f],
+    ],
+    // Pocket
+    [
+        [0xc5, 0xca, 0xa4],
+        [0x8c, 0x92, 0x6b],
+        [0x4a, 0x51, 0x38],
+        [0x18, 0x18, 0x18],
+    ],
+    // Light
+    [
+        [0x00, 0xb5, 0x81],
+        [0x00, 0x9a, 0x71],
+        [0x00, 0x69, 0x4a],
+        [0x00, 0x51, 0x3a],
+    ],
+    // CGB boot palettes for DMG games: brown, blue and red
+    [
+        [0xff, 0xff, 0xff],
+        [0xff, 0xad, 0x63],
+        [0x84, 0x31, 0x00],
+        [0x00, 0x00, 0x00],
+    ],
+    [
+        [0xff, 0xff, 0xff],
+        [0x63, 0xa5, 0xff],
+        [0x00, 0x00, 0xff],
+        [0x00, 0x00, 0x00],
+    ],
+    [
+        [0xff, 0xff, 0xff],
+        [0xff, 0x84, 0x84],
+        [0x94, 0x3a, 0x3a],
+        [0x00, 0x00, 0x00],
+    ],
+];
+
+fn luma(p: &Rgba<u8>) -> f32 {
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+/// Luma of `img` flattened onto its mean opaque color, so filters over a
+/// neighbourhood don't pick up the edge of the silhouette.
+fn opaque_luma(img: &DynamicImage) -> image::GrayImage {
+    let img = img.to_rgba8();
+    let (mut sum, mut weight) = ([0f32; 3], 0f32);
+    for p in img.pixels() {
+        let a = p[3] as f32 / 255.0;
+        for c in 0..3 {
+            sum[c] += p[c] as f32 * a;
+        }
+        weight += a;
+    }
+    let mean = sum.map(|s| if weight > 0.0 { s / weight } else { 0.0 });
+    image::GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let p = img.get_pixel(x, y);
+        let a = p[3] as f32 / 255.0;
+        let mut flat = Rgba([0, 0, 0, 0xff]);
+        for c in 0..3 {
+            flat[c] = (p[c] as f32 * a + mean[c] * (1.0 - a)).round() as u8;
+        }
+        image::Luma([luma(&flat).round() as u8])
+    })
+}
+
+/// Resamples `img` so each output pixel `(x, y)` is read from `f(x, y)`.
+/// Sampling is done premultiplied so transparent pixels don't bleed their
+/// (black) color into the edges, and pixels mapped outside are transparent.
+fn remap<F>(img: &DynamicImage, f: F) -> image::RgbaImage
+where
+    F: Fn(f32, f32) -> (f32, f32) + Sync + Send,
+{
+    use imageproc::geometric_transformations::{warp_with, Interpolation};
+    let mut src = img.to_rgba8();
+    premultiply(&mut src);
+    let mut out = warp_with(&src, f, Interpolation::Bilinear, Rgba([0, 0, 0, 0]));
+    unpremultiply(&mut out);
+    out
 }
 
 pub fn rgba<R>(mut rng: R, alpha: Option<u8>) -> Rgba<u8>
@@ -25,6 +196,7 @@ where
     ])
 }
 
+/// Gives `target` the alpha of `mask`, clearing fully transparent pixels.
 pub fn mask(target: &mut image::RgbaImage, mask: &DynamicImage) {
     target
         .pixels_mut()
@@ -32,10 +204,28 @@ pub fn mask(target: &mut image::RgbaImage, mask: &DynamicImage) {
         .for_each(|(p, m)| {
             if m[3] == 0 {
                 *p = Rgba([0, 0, 0, 0]);
+            } else {
+                p[3] = m[3];
             }
         });
 }
 
+fn premultiply(img: &mut image::RgbaImage) {
+    img.pixels_mut().for_each(|p| {
+        for c in 0..3 {
+            p[c] = ((p[c] as u32 * p[3] as u32 + 127) / 255) as u8;
+        }
+    });
+}
+
+fn unpremultiply(img: &mut image::RgbaImage) {
+    img.pixels_mut().filter(|p| p[3] > 0).for_each(|p| {
+        for c in 0..3 {
+            p[c] = ((p[c] as u32 * 255 + p[3] as u32 / 2) / p[3] as u32).min(255) as u8;
+        }
+    });
+}
+
 pub fn projection<R>(mut rng: R, img_dim: (u32, u32), screen_dim: (u32, u32)) -> Projection
 where
     R: Rng,
@@ -50,52 +240,276 @@ where
         * Projection::translate(-(img_dim.0 as f32) / 2.0, -(img_dim.1 as f32) / 2.0)
 }
 
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TransformConfig {
+    /// Range of how many transforms to apply to each image.
+    pub count: (u32, u32),
+    /// Fixed chains, e.g. `presets = ["blur(3) | posterize(4)"]`. When any
+    /// are given each image gets one of them instead of a random chain.
+    pub presets: Vec<Chain>,
+    /// Overrides of each transform's alpha handling, e.g.
+    /// `alpha = { blur = "respect" }`.
+    pub alpha: HashMap<String, Alpha>,
+    #[serde(flatten)]
+    pub variants: Variants,
+}
+
+impl Default for TransformConfig {
+    fn default() -> Self {
+        TransformConfig {
+            count: (1, 10),
+            presets: vec![],
+            alpha: HashMap::new(),
+            variants: Variants::default(),
+        }
+    }
+}
+
+impl TransformConfig {
+    pub fn count<R>(&self, mut rng: R) -> u32
+    where
+        R: Rng,
+    {
+        if self.count.0 < self.count.1 {
+            rng.gen_range(self.count.0..self.count.1)
+        } else {
+            self.count.0
+        }
+    }
+
+    pub fn chain<R>(&self, mut rng: R, height: u32, width: u32) -> Chain
+    where
+        R: Rng,
+    {
+        use rand::seq::SliceRandom;
+        if let Some(preset) = self.presets.choose(&mut rng) {
+            return preset.clone();
+        }
+        Chain(
+            (0..self.count(&mut rng))
+                .filter_map(|_| {
+                    let transform = Transform::random(&mut rng, &self.variants, height, width)?;
+                    let alpha = self
+                        .alpha
+                        .get(transform.name())
+                        .copied()
+                        .unwrap_or_else(|| transform.default_alpha());
+                    Some(Step { transform, alpha })
+                })
+                .collect(),
+        )
+    }
+}
+
+type Generator<R> = Box<dyn Fn(&mut R, Params) -> Transform>;
+
 impl Transform {
-    pub fn random<R>(mut rng: R, _height: u32, _width: u32) -> Transform
+    /// `None` when the config disables every transform.
+    pub fn random<R>(mut rng: R, cfg: &Variants, _height: u32, _width: u32) -> Option<Transform>
     where
         R: Rng,
     {
-        let v: Vec<(_, Box<dyn Fn(&mut R) -> _>)> = vec![
+        let v: Vec<(_, _, Generator<R>)> = vec![
             (
+                "edges",
                 1,
-                Box::new(|mut rng| {
+                Box::new(|mut rng, p| {
                     Transform::Edges(
-                        rng.gen_range(0.0..0.3),
-                        rng.gen_range(0.7..1.0),
+                        p.f32(&mut rng, "low", 0.0..0.3),
+                        p.f32(&mut rng, "high", 0.7..1.0),
                         rgba(&mut rng, Some(0xff)),
                         rgba(&mut rng, Some(0xff)),
                     )
                 }),
             ),
             (
+                "overlay_edges",
                 1,
-                Box::new(|mut rng| {
+                Box::new(|mut rng, p| {
                     Transform::OverlayEdges(
-                        rng.gen_range(0.0..0.3),
-                        rng.gen_range(0.7..1.0),
+                        p.f32(&mut rng, "low", 0.0..0.3),
+                        p.f32(&mut rng, "high", 0.7..1.0),
                         rgba(&mut rng, Some(0xff)),
                     )
                 }),
             ),
             (
+                "noise",
                 5,
-                Box::new(|rng| {
-                    Transform::Noise(rng.gen_range(0.0..0.3), rng.gen_range(0.7..1.0), rng.gen())
+                Box::new(|mut rng, p| {
+                    Transform::Noise(
+                        p.f64(&mut rng, "mean", 0.0..0.3),
+                        p.f64(&mut rng, "stddev", 0.7..1.0),
+                        rng.gen(),
+                    )
                 }),
             ),
             (
+                "threshold",
                 1,
-                Box::new(|mut rng| {
+                Box::new(|mut rng, p| {
                     Transform::Threshold(
-                        rng.gen_range(1..40),
+                        p.u32(&mut rng, "radius", 1..40),
                         rgba(&mut rng, Some(0xff)),
                         rgba(&mut rng, Some(0xff)),
                     )
                 }),
             ),
-            (1, Box::new(|rng| Transform::Blur(rng.gen_range(0.0..10.0)))),
+            (
+                "blur",
+                1,
+                Box::new(|mut rng, p| Transform::Blur(p.f32(&mut rng, "sigma", 0.0..10.0))),
+            ),
+            (
+                "hue_rotate",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::HueRotate(p.u32(&mut rng, "degrees", 0..360) as i32)
+                }),
+            ),
+            (
+                "saturation",
+                1,
+                Box::new(|mut rng, p| Transform::Saturation(p.f32(&mut rng, "factor", 0.0..2.5))),
+            ),
+            (
+                "contrast",
+                1,
+                Box::new(|mut rng, p| Transform::Contrast(p.f32(&mut rng, "amount", -50.0..80.0))),
+            ),
+            (
+                "brightness",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::Brightness(p.f32(&mut rng, "amount", -60.0..60.0) as i32)
+                }),
+            ),
+            (
+                "posterize",
+                1,
+                Box::new(|mut rng, p| Transform::Posterize(p.u32(&mut rng, "levels", 2..6) as u8)),
+            ),
+            (
+                "gradient_map",
+                1,
+                Box::new(|mut rng, _| {
+                    Transform::GradientMap(rgba(&mut rng, Some(0xff)), rgba(&mut rng, Some(0xff)))
+                }),
+            ),
+            (
+                "palette",
+                1,
+                Box::new(|rng, _| Transform::Palette(rng.gen_range(0..PALETTES.len()))),
+            ),
+            (
+                "swirl",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::Swirl(
+                        p.f32(&mut rng, "strength", -6.0..6.0),
+                        p.f32(&mut rng, "radius", 0.3..1.0),
+                    )
+                }),
+            ),
+            (
+                "wave",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::Wave(
+                        p.f32(&mut rng, "amplitude", 2.0..20.0),
+                        p.f32(&mut rng, "wavelength", 20.0..120.0),
+                        rng.gen_range(0.0..2.0 * std::f32::consts::PI),
+                    )
+                }),
+            ),
+            (
+                "ripple",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::Ripple(
+                        p.f32(&mut rng, "amplitude", 2.0..12.0),
+                        p.f32(&mut rng, "wavelength", 10.0..60.0),
+                    )
+                }),
+            ),
+            (
+                "fisheye",
+                1,
+                Box::new(|mut rng, p| Transform::Fisheye(p.f32(&mut rng, "strength", 1.2..2.5))),
+            ),
+            ("polar", 1, Box::new(|_, _| Transform::Polar)),
+            (
+                "kaleidoscope",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::Kaleidoscope(
+                        p.u32(&mut rng, "segments", 3..12),
+                        rng.gen_range(0.0..2.0 * std::f32::consts::PI),
+                    )
+                }),
+            ),
+            (
+                "tile",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::Tile(
+                        p.u32(&mut rng, "columns", 2..5),
+                        p.u32(&mut rng, "rows", 2..5),
+                        rng.gen(),
+                    )
+                }),
+            ),
+            (
+                "channel_shift",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::ChannelShift(p.u32(&mut rng, "offset", 2..16), rng.gen())
+                }),
+            ),
+            (
+                "scanline_jitter",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::ScanlineJitter(
+                        p.u32(&mut rng, "shift", 4..40),
+                        p.f32(&mut rng, "chance", 0.05..0.4),
+                        rng.gen(),
+                    )
+                }),
+            ),
+            (
+                "pixel_sort",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::PixelSort(
+                        p.f32(&mut rng, "low", 0.1..0.4),
+                        p.f32(&mut rng, "high", 0.6..0.95),
+                        rng.gen(),
+                    )
+                }),
+            ),
+            (
+                "block_shift",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::BlockShift(
+                        p.u32(&mut rng, "blocks", 4..30),
+                        p.u32(&mut rng, "size", 8..60),
+                        rng.gen(),
+                    )
+                }),
+            ),
+            (
+                "data_bend",
+                1,
+                Box::new(|mut rng, p| {
+                    Transform::DataBend(p.f32(&mut rng, "rows", 0.01..0.15), rng.gen())
+                }),
+            ),
         ];
-        (v.choose_weighted(&mut rng, |e| e.0).expect("valid").1)(&mut rng)
+        cfg.choose(&mut rng, &v)
+            .map(|(name, _, f)| f(&mut rng, cfg.params(name)))
     }
 }
 
@@ -110,10 +524,14 @@ impl Transformable {
     pub fn into_inner(self) -> DynamicImage {
         self.image
     }
-    pub fn transform(&mut self, t: Transform) {
-        match t {
+    fn center(&self) -> (f32, f32) {
+        let (w, h) = self.image.dimensions();
+        (w as f32 / 2.0, h as f32 / 2.0)
+    }
+    pub fn transform(&mut self, t: Transform, alpha: Alpha) {
+        let mut image = match t {
             Transform::Edges(low, high, fg_color, bg_color) => {
-                let gray = self.image.to_luma8();
+                let gray = opaque_luma(&self.image);
                 let tmp = DynamicImage::ImageLuma8(imageproc::edges::canny(&gray, low, high));
                 let mut rgb8 = tmp.to_rgba8();
                 rgb8.pixels_mut().for_each(|p| {
@@ -123,11 +541,10 @@ impl Transformable {
                         *p = fg_color;
                     }
                 });
-                mask(&mut rgb8, &self.image);
-                self.image = DynamicImage::ImageRgba8(rgb8);
+                rgb8
             }
             Transform::OverlayEdges(low, high, fg_color) => {
-                let gray = self.image.to_luma8();
+                let gray = opaque_luma(&self.image);
                 let tmp = DynamicImage::ImageLuma8(imageproc::edges::canny(&gray, low, high));
                 let mut rgb8 = tmp.to_rgba8();
                 rgb8.pixels_mut().for_each(|p| {
@@ -138,16 +555,17 @@ impl Transformable {
                     }
                 });
 
-                image::imageops::overlay(&mut self.image, &rgb8, 0, 0);
+                let mut image = self.image.to_rgba8();
+                image::imageops::overlay(&mut image, &rgb8, 0, 0);
+                image
             }
             Transform::Noise(mean, stddev, seed) => {
                 let mut image = self.image.to_rgba8();
                 imageproc::noise::gaussian_noise_mut(&mut image, mean, stddev, seed);
-                mask(&mut image, &self.image);
-                self.image = DynamicImage::ImageRgba8(image);
+                image
             }
             Transform::Threshold(radius, fg_color, bg_color) => {
-                let image = self.image.to_luma8();
+                let image = opaque_luma(&self.image);
                 let image = imageproc::contrast::adaptive_threshold(&image, radius);
                 let mut rgb8 = DynamicImage::ImageLuma8(image).into_rgba8();
                 rgb8.pixels_mut().for_each(|p| {
@@ -157,14 +575,175 @@ impl Transformable {
                         *p = fg_color;
                     }
                 });
-                mask(&mut rgb8, &self.image);
-                self.image = DynamicImage::ImageRgba8(rgb8);
+                rgb8
             }
             Transform::Blur(sigma) => {
-                let image = self.image.to_rgba8();
-                let image = imageproc::filter::gaussian_blur_f32(&image, sigma);
-                self.image = DynamicImage::ImageRgba8(image);
+                let mut image = self.image.to_rgba8();
+                premultiply(&mut image);
+                let mut image = imageproc::filter::gaussian_blur_f32(&image, sigma);
+                unpremultiply(&mut image);
+                image
+            }
+            Transform::HueRotate(degrees) => image::imageops::huerotate(&self.image, degrees),
+            Transform::Saturation(factor) => {
+                let mut image = self.image.to_rgba8();
+                image.pixels_mut().for_each(|p| {
+                    let l = luma(p);
+                    for c in 0..3 {
+                        p[c] = (l + (p[c] as f32 - l) * factor).round().clamp(0.0, 255.0) as u8;
+                    }
+                });
+                image
+            }
+            Transform::Contrast(amount) => self.image.adjust_contrast(amount).into_rgba8(),
+            Transform::Brightness(amount) => self.image.brighten(amount).into_rgba8(),
+            Transform::Posterize(levels) => {
+                let steps = levels.max(2) as f32 - 1.0;
+                let mut image = self.image.to_rgba8();
+                image.pixels_mut().for_each(|p| {
+                    for c in 0..3 {
+                        p[c] = ((p[c] as f32 / 255.0 * steps).round() / steps * 255.0) as u8;
+                    }
+                });
+                image
+            }
+            Transform::GradientMap(dark, light) => {
+                let mut image = self.image.to_rgba8();
+                image.pixels_mut().for_each(|p| {
+                    let t = luma(p) / 255.0;
+                    for c in 0..3 {
+                        p[c] = (dark[c] as f32 * (1.0 - t) + light[c] as f32 * t).round() as u8;
+                    }
+                });
+                image
+            }
+            Transform::Palette(idx) => {
+                let palette = &PALETTES[idx % PALETTES.len()];
+                let mut image = self.image.to_rgba8();
+                image.pixels_mut().for_each(|p| {
+                    let shade = 3 - ((luma(p) / 256.0 * 4.0) as usize).min(3);
+                    let [r, g, b] = palette[shade];
+                    *p = Rgba([r, g, b, p[3]]);
+                });
+                image
+            }
+            Transform::Swirl(strength, radius) => {
+                let (cx, cy) = self.center();
+                let radius = radius * cx.min(cy);
+                remap(&self.image, |x, y| {
+                    let (dx, dy) = (x - cx, y - cy);
+                    let r = dx.hypot(dy);
+                    if r >= radius {
+                        return (x, y);
+                    }
+                    let t = 1.0 - r / radius;
+                    let angle = dy.atan2(dx) + strength * t * t;
+                    (cx + r * angle.cos(), cy + r * angle.sin())
+                })
+            }
+            Transform::Wave(amplitude, wavelength, phase) => {
+                let k = 2.0 * std::f32::consts::PI / wavelength.max(1.0);
+                remap(&self.image, |x, y| {
+                    (
+                        x + amplitude * (k * y + phase).sin(),
+                        y + amplitude * (k * x + phase).sin(),
+                    )
+                })
+            }
+            Transform::Ripple(amplitude, wavelength) => {
+                let (cx, cy) = self.center();
+                let k = 2.0 * std::f32::consts::PI / wavelength.max(1.0);
+                remap(&self.image, |x, y| {
+                    let (dx, dy) = (x - cx, y - cy);
+                    let r = dx.hypot(dy);
+                    if r == 0.0 {
+                        return (x, y);
+                    }
+                    let scale = (r + amplitude * (k * r).sin()) / r;
+                    (cx + dx * scale, cy + dy * scale)
+                })
+            }
+            Transform::Fisheye(strength) => {
+                let (cx, cy) = self.center();
+                let max = cx.hypot(cy);
+                remap(&self.image, |x, y| {
+                    let (dx, dy) = (x - cx, y - cy);
+                    let r = dx.hypot(dy) / max;
+                    // Sampling closer to the center magnifies it.
+                    let scale = r.powf(strength - 1.0);
+                    (cx + dx * scale, cy + dy * scale)
+                })
+            }
+            Transform::Polar => {
+                let (w, h) = self.image.dimensions();
+                let (cx, cy) = self.center();
+                let max = cx.min(cy);
+                remap(&self.image, |x, y| {
+                    let angle = x / w as f32 * 2.0 * std::f32::consts::PI;
+                    let r = y / h as f32 * max;
+                    (cx + r * angle.cos(), cy + r * angle.sin())
+                })
+            }
+            Transform::Kaleidoscope(segments, rotation) => {
+                let (cx, cy) = self.center();
+                let segment = 2.0 * std::f32::consts::PI / segments.max(1) as f32;
+                remap(&self.image, |x, y| {
+                    let (dx, dy) = (x - cx, y - cy);
+                    let r = dx.hypot(dy);
+                    let mut angle = (dy.atan2(dx) - rotation).rem_euclid(segment);
+                    // Mirror every other half segment so the seams line up.
+                    if angle > segment / 2.0 {
+                        angle = segment - angle;
+                    }
+                    let angle = angle + rotation;
+                    (cx + r * angle.cos(), cy + r * angle.sin())
+                })
+            }
+            Transform::Tile(columns, rows, mirror) => {
+                let (w, h) = self.image.dimensions();
+                let (w, h) = (w as f32, h as f32);
+                let (columns, rows) = (columns.max(1) as f32, rows.max(1) as f32);
+                let wrap = |v: f32, tiles: f32, size: f32| {
+                    let t = v * tiles / size;
+                    let f = t.fract();
+                    let f = if mirror && t as u32 % 2 == 1 {
+                        1.0 - f
+                    } else {
+                        f
+                    };
+                    (f * size).min(size - 1.0)
+                };
+                remap(&self.image, |x, y| (wrap(x, columns, w), wrap(y, rows, h)))
+            }
+            Transform::ChannelShift(offset, seed) => {
+                let mut image = self.image.to_rgba8();
+                glitch::channel_shift(&mut image, offset, seed);
+                image
+            }
+            Transform::ScanlineJitter(shift, chance, seed) => {
+                let mut image = self.image.to_rgba8();
+                glitch::scanline_jitter(&mut image, shift, chance, seed);
+                image
+            }
+            Transform::PixelSort(low, high, vertical) => {
+                let mut image = self.image.to_rgba8();
+                glitch::pixel_sort(&mut image, low, high, vertical);
+                image
+            }
+            Transform::BlockShift(blocks, size, seed) => {
+                let mut image = self.image.to_rgba8();
+                glitch::block_shift(&mut image, blocks, size, seed);
+                image
+            }
+            Transform::DataBend(rows, seed) => {
+                let mut image = self.image.to_rgba8();
+                glitch::data_bend(&mut image, rows, seed);
+                image
             }
+        };
+        if alpha == Alpha::Respect {
+            mask(&mut image, &self.image);
         }
+        self.image = DynamicImage::ImageRgba8(image);
     }
 }
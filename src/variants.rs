@@ -0,0 +1,97 @@
+use rand::Rng;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Config for one entry of a randomly chosen menu, e.g.
+///
+/// ```toml
+/// [background.perlin]
+/// weight = 3
+/// scale = [20.0, 200.0]
+/// ```
+///
+/// Any key other than `enabled` and `weight` is a `[min, max]` parameter range.
+#[derive(Deserialize, Default, Clone, Debug)]
+pub struct Variant {
+    pub enabled: Option<bool>,
+    pub weight: Option<u32>,
+    #[serde(flatten)]
+    pub params: HashMap<String, (f64, f64)>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(transparent)]
+pub struct Variants(HashMap<String, Variant>);
+
+impl Variants {
+    pub fn weight(&self, name: &str, default: u32) -> u32 {
+        match self.0.get(name) {
+            Some(Variant {
+                enabled: Some(false),
+                ..
+            }) => 0,
+            Some(Variant {
+                weight: Some(w), ..
+            }) => *w,
+            _ => default,
+        }
+    }
+
+    pub fn params(&self, name: &str) -> Params<'_> {
+        Params {
+            variant: self.0.get(name),
+        }
+    }
+
+    /// Picks an entry from `menu` of `(name, default weight, item)`,
+    /// honoring any weight overrides in the config.
+    pub fn choose<'a, R, T>(
+        &self,
+        mut rng: R,
+        menu: &'a [(&'static str, u32, T)],
+    ) -> Option<&'a (&'static str, u32, T)>
+    where
+        R: Rng,
+    {
+        use rand::seq::SliceRandom;
+        menu.choose_weighted(&mut rng, |(name, w, _)| self.weight(name, *w))
+            .ok()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Params<'a> {
+    variant: Option<&'a Variant>,
+}
+
+impl<'a> Params<'a> {
+    fn range(&self, param: &str) -> Option<(f64, f64)> {
+        self.variant.and_then(|v| v.params.get(param)).copied()
+    }
+
+    pub fn f64<R: Rng>(&self, mut rng: R, param: &str, default: Range<f64>) -> f64 {
+        let (lo, hi) = self.range(param).unwrap_or((default.start, default.end));
+        if lo < hi {
+            rng.gen_range(lo..hi)
+        } else {
+            lo
+        }
+    }
+
+    pub fn f32<R: Rng>(&self, rng: R, param: &str, default: Range<f32>) -> f32 {
+        self.f64(rng, param, default.start as f64..default.end as f64) as f32
+    }
+
+    pub fn u32<R: Rng>(&self, mut rng: R, param: &str, default: Range<u32>) -> u32 {
+        let (lo, hi) = self
+            .range(param)
+            .map(|(lo, hi)| (lo.max(0.0) as u32, hi.max(0.0) as u32))
+            .unwrap_or((default.start, default.end));
+        if lo < hi {
+            rng.gen_range(lo..hi)
+        } else {
+            lo
+        }
+    }
+}